@@ -0,0 +1,11 @@
+use std::env;
+
+fn main() {
+    // Realia is a proc macro, so it's built for the same host that will run it.
+    // Remember that host in case rustc can't be run to ask for it later.
+    println!(
+        "cargo:rustc-env=REALIA_HOST_TARGET={}",
+        env::var("TARGET").expect("TARGET is set for build scripts")
+    );
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,272 @@
+//! On-disk cache of `cargo metadata` output.
+//!
+//! Snapshots are stored in a `realia` folder next to the folder where rustc puts
+//! the compiled crate (e.g., `target/debug/realia`) and are keyed by a hash of
+//! `Cargo.lock`, the relevant
+//! `Cargo.toml` files, the target triple, and the enabled features, so they're
+//! reused across builds until one of those changes. Each snapshot also records
+//! the other files that can affect the output (the manifests of local packages
+//! and Cargo's configuration files), and it's discarded if any of them change.
+//! Only the latest snapshot for each crate, target, and set of features is kept.
+//! Set `REALIA_NO_CACHE` to bypass the cache entirely, or delete the `realia`
+//! folder to invalidate it.
+
+use crate::metadata::Query;
+use serde_json::Value;
+use std::{
+    collections::hash_map::DefaultHasher,
+    env, fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+/// Bumped whenever the snapshot contents change, so that old snapshots aren't reused.
+const SNAPSHOT_FORMAT: u32 = 3;
+
+/// The snapshot field that lists other files the output depends on, with a hash of each.
+const INPUTS_FIELD: &str = "realia_inputs";
+
+/// Target fields that the checks use. Everything else is dropped.
+const USED_TARGET_FIELDS: &[&str] = &["name", "kind", "src_path"];
+
+/// Package fields that none of the checks use. They're dropped from the
+/// snapshot to keep it small.
+const UNUSED_PACKAGE_FIELDS: &[&str] = &[
+    "authors",
+    "categories",
+    "description",
+    "documentation",
+    "homepage",
+    "keywords",
+    "license",
+    "license_file",
+    "readme",
+    "repository",
+];
+
+/// The folder for snapshots, or `None` if the cache is disabled or the build's
+/// target directory isn't known. This goes by rustc's `--out-dir` rather than
+/// guessing, so that it respects `CARGO_TARGET_DIR` and `build.target-dir`,
+/// and so that a registry dependency using Realia writes its snapshots to the
+/// project being built instead of to its own source folder in Cargo's home.
+pub fn dir() -> Option<PathBuf> {
+    if env::var_os("REALIA_NO_CACHE").is_some() {
+        return None;
+    }
+    let out_dir = crate::metadata::check_arg("--out-dir")?;
+    Some(Path::new(&out_dir).parent()?.join("realia"))
+}
+
+/// Returns the cached `cargo metadata` output from `dir`, if there is an up-to-date one.
+pub fn load(dir: &Path, query: &Query) -> Option<Value> {
+    let snapshot: Value = serde_json::from_str(&fs::read_to_string(snapshot_path(dir, query)?.0).ok()?).ok()?;
+    let inputs = snapshot.get(INPUTS_FIELD)?.as_array()?;
+    let fresh = inputs.iter().all(|input| {
+        match (
+            input.get(0).and_then(Value::as_str),
+            input.get(1).and_then(Value::as_str),
+        ) {
+            (Some(path), Some(hash)) => hash_file(Path::new(path)) == hash,
+            _ => false,
+        }
+    });
+    if fresh {
+        Some(snapshot)
+    } else {
+        None
+    }
+}
+
+/// Saves a trimmed copy of the `cargo metadata` output in `dir`.
+/// This is best-effort, so any failure is ignored.
+pub fn store(dir: &Path, query: &Query, output: &str) {
+    let _ = try_store(dir, query, output);
+}
+
+fn try_store(dir: &Path, query: &Query, output: &str) -> Option<()> {
+    let (path, prefix) = snapshot_path(dir, query)?;
+    let mut snapshot: Value = serde_json::from_str(output).ok()?;
+    let inputs: Vec<Value> = inputs(query, &snapshot)
+        .into_iter()
+        .map(|path| {
+            let hash = hash_file(&path);
+            Value::from(vec![
+                Value::from(path.to_string_lossy().into_owned()),
+                Value::from(hash),
+            ])
+        })
+        .collect();
+    trim(&mut snapshot);
+    snapshot
+        .as_object_mut()?
+        .insert(INPUTS_FIELD.to_string(), Value::from(inputs));
+
+    fs::create_dir_all(dir).ok()?;
+    // Write to a temporary file first so that a concurrent build never
+    // reads a partially written snapshot.
+    let temp = path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::write(&temp, serde_json::to_string(&snapshot).ok()?).ok()?;
+    fs::rename(&temp, &path).ok()?;
+    prune(dir, &path, &prefix);
+    Some(())
+}
+
+/// Lists the files, other than the ones in the snapshot's name, that can change
+/// the output without changing the lock file: the manifests of local packages
+/// (e.g., their features or `[package.metadata]`) and Cargo's configuration files
+/// (e.g., `[patch]` or source replacement). Files that don't exist are included
+/// too, since creating them can also change the output.
+fn inputs(query: &Query, snapshot: &Value) -> Vec<PathBuf> {
+    let mut inputs: Vec<PathBuf> = snapshot
+        .get("packages")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|package| package.get("source").map_or(true, Value::is_null))
+        .filter_map(|package| package.get("manifest_path").and_then(Value::as_str))
+        .map(PathBuf::from)
+        .collect();
+    let config_dirs = Path::new(&query.manifest_dir)
+        .ancestors()
+        .map(|dir| dir.join(".cargo"))
+        .chain(crate::source::cargo_home());
+    for dir in config_dirs {
+        inputs.push(dir.join("config.toml"));
+        inputs.push(dir.join("config"));
+    }
+    inputs
+}
+
+/// Hashes a file's content, or returns an empty string if it can't be read.
+fn hash_file(path: &Path) -> String {
+    match fs::read(path) {
+        Ok(content) => {
+            let mut hasher = DefaultHasher::new();
+            content.hash(&mut hasher);
+            format!("{:016x}", hasher.finish())
+        }
+        Err(_) => String::new(),
+    }
+}
+
+/// Deletes older snapshots for the same query, along with any snapshots
+/// from before the names included the query.
+fn prune(dir: &Path, current: &Path, prefix: &str) {
+    for entry in fs::read_dir(dir).into_iter().flatten().filter_map(Result::ok) {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let legacy = name
+            .strip_prefix("metadata-")
+            .and_then(|x| x.strip_suffix(".json"))
+            .map_or(false, |hash| !hash.contains('-'));
+        if path != current && (legacy || name.starts_with(prefix) && name.ends_with(".json")) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+fn trim(snapshot: &mut Value) {
+    if let Some(packages) = snapshot.get_mut("packages").and_then(Value::as_array_mut) {
+        for package in packages.iter_mut().filter_map(Value::as_object_mut) {
+            for field in UNUSED_PACKAGE_FIELDS {
+                package.remove(*field);
+            }
+            if let Some(targets) = package.get_mut("targets").and_then(Value::as_array_mut) {
+                for target in targets.iter_mut().filter_map(Value::as_object_mut) {
+                    target.retain(|field, _| USED_TARGET_FIELDS.contains(&field.as_str()));
+                }
+            }
+        }
+    }
+}
+
+/// Returns the snapshot's path in `dir`, along with the start of the file name
+/// that it shares with older snapshots for the same query.
+fn snapshot_path(dir: &Path, query: &Query) -> Option<(PathBuf, String)> {
+    let manifest_dir = Path::new(&query.manifest_dir);
+    let lock_dir = manifest_dir.ancestors().find(|dir| dir.join("Cargo.lock").is_file())?;
+
+    let mut hasher = DefaultHasher::new();
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    SNAPSHOT_FORMAT.hash(&mut hasher);
+    manifest_dir.hash(&mut hasher);
+    query.target.hash(&mut hasher);
+    query.features.hash(&mut hasher);
+    let prefix = format!("metadata-{:016x}-", hasher.finish());
+
+    let mut hasher = DefaultHasher::new();
+    fs::read(lock_dir.join("Cargo.lock")).ok()?.hash(&mut hasher);
+    fs::read(manifest_dir.join("Cargo.toml")).ok()?.hash(&mut hasher);
+    if lock_dir != manifest_dir {
+        // The workspace manifest can change the resolution too, e.g. via `[patch]`.
+        fs::read(lock_dir.join("Cargo.toml")).ok()?.hash(&mut hasher);
+    }
+
+    let path = dir.join(format!("{}{:016x}.json", prefix, hasher.finish()));
+    Some((path, prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn snapshots_are_invalidated_by_local_manifests_and_pruned() {
+        let root = env::temp_dir().join(format!("realia-cache-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        write(&root.join("Cargo.toml"), "[package]\nname = \"a\"\n");
+        write(&root.join("Cargo.lock"), "version = 3\n");
+        write(&root.join("b/Cargo.toml"), "[package]\nname = \"b\"\n");
+
+        let query = Query {
+            manifest_dir: root.to_string_lossy().into_owned(),
+            target: "x86_64-unknown-linux-gnu".to_string(),
+            features: None,
+        };
+        let output = serde_json::json!({
+            "packages": [
+                { "name": "b", "source": null, "manifest_path": root.join("b/Cargo.toml") },
+                { "name": "c", "source": "registry+https://github.com/rust-lang/crates.io-index",
+                  "manifest_path": root.join("c/Cargo.toml") },
+            ],
+        })
+        .to_string();
+
+        let dir = root.join("target/debug/realia");
+        write(&dir.join("metadata-0123456789abcdef.json"), "{}");
+
+        store(&dir, &query, &output);
+        let snapshot = load(&dir, &query).expect("snapshot should be fresh");
+        assert_eq!(snapshot["packages"][0]["name"], "b");
+        let inputs = snapshot[INPUTS_FIELD].to_string();
+        assert!(inputs.contains("Cargo.toml"));
+        assert!(!inputs.contains("c/Cargo.toml"));
+
+        // A local package's manifest can change without changing the lock file.
+        write(&root.join("b/Cargo.toml"), "[package]\nname = \"b\"\nlinks = \"z\"\n");
+        assert!(load(&dir, &query).is_none());
+
+        // So can Cargo's configuration.
+        store(&dir, &query, &output);
+        assert!(load(&dir, &query).is_some());
+        write(&root.join(".cargo/config.toml"), "[patch.crates-io]\n");
+        assert!(load(&dir, &query).is_none());
+
+        // Changing the lock file gives the snapshot a new name, and the old one is removed.
+        write(&root.join("Cargo.lock"), "version = 4\n");
+        store(&dir, &query, &output);
+        let snapshots: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .filter_map(Result::ok)
+            .filter(|x| x.file_name().to_string_lossy().starts_with("metadata-"))
+            .collect();
+        assert_eq!(snapshots.len(), 1);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,20 +1,67 @@
+use crate::metadata::{Cache, Query, Unknowns};
 use anyhow::{anyhow, Error};
+use cargo_metadata::{Dependency, DependencyKind, Metadata, Node, NodeDep, Package, PackageId};
+use std::{
+    collections::{HashSet, VecDeque},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
 
+/// Cache of dependency lists, keyed by the metadata query, anchor,
+/// and whether the list is transitive.
+static DEPS: Cache<(Query, String, bool), Arc<Vec<DepInfo>>> = Mutex::new(None);
+
+#[derive(Clone)]
 pub struct DepInfo {
     pub name: String,
+    /// The local name of the dependency, if it was renamed with `package = "..."`.
+    pub rename: Option<String>,
     pub version: String,
+    /// The source declared in the manifest that depends on this package,
+    /// which is the anchor's manifest for direct dependencies.
     pub source: Option<String>,
+    /// The source that the package was actually resolved from.
+    pub resolved_source: Option<String>,
+    pub manifest_path: PathBuf,
+    /// How the anchor depends on this. For transitive dependencies, this is
+    /// the kind of the anchor's direct dependency that pulled it in.
+    /// This is `None` for the anchor itself.
+    pub kind: Option<DependencyKind>,
+    /// The features that are enabled on the package in the resolved graph.
+    pub features: Vec<String>,
+    /// The native library that the package declares with `links = "..."`.
+    pub links: Option<String>,
+    /// The package's `[package.metadata]` table.
+    pub metadata: serde_json::Value,
+    /// The minimum supported Rust version that the package declares with `rust-version = "..."`.
+    pub rust_version: Option<String>,
+    /// The fields that the fallback for `cargo metadata` couldn't determine, like `features`.
+    /// `linked` means that the package might not actually be part of the build.
+    pub unknown: Vec<String>,
 }
 
-pub fn get_deps(anchor_name: &str) -> Result<Vec<DepInfo>, Error> {
+/// Lists the anchor itself along with either its direct dependencies
+/// or, if `transitive`, every package in their normal dependency closure.
+/// This includes all kinds of dependencies; see [`linked_kinds`].
+pub fn get_deps(anchor_name: &str, transitive: bool) -> Result<Arc<Vec<DepInfo>>, Error> {
+    let query = Query::current()?;
+    let modified = query.modified();
+    crate::metadata::memoize(&DEPS, (query, anchor_name.to_string(), transitive), modified, || {
+        if transitive {
+            find_transitive_deps(anchor_name).map(Arc::new)
+        } else {
+            find_deps(anchor_name).map(Arc::new)
+        }
+    })
+}
+
+fn find_deps(anchor_name: &str) -> Result<Vec<DepInfo>, Error> {
     let metadata = crate::metadata::get_metadata()?;
+    let rust_versions = crate::metadata::get_rust_versions()?;
+    let unknowns = crate::metadata::get_unknowns()?;
 
-    let position = metadata
-        .packages
-        .iter()
-        .position(|x| x.name == anchor_name)
-        .ok_or_else(|| anyhow!("Cannot find metadata for the anchor package"))?;
-    let anchor = &metadata.packages[position];
+    let anchor = find_anchor(&metadata, anchor_name)?;
+    check_declarations(&unknowns, anchor)?;
 
     // If we're in the middle of `cargo publish`, we need to delete the lock
     // file that was generated by the metadata command, or else Cargo will
@@ -29,21 +76,38 @@ pub fn get_deps(anchor_name: &str) -> Result<Vec<DepInfo>, Error> {
 
     let mut deps = vec![DepInfo {
         name: anchor.name.to_owned(),
+        rename: None,
         version: anchor.version.to_string(),
         source: anchor.source.as_ref().map(|source| source.to_string()),
+        resolved_source: anchor.source.as_ref().map(|source| source.to_string()),
+        manifest_path: anchor.manifest_path.clone(),
+        kind: None,
+        features: resolved_features(&metadata, &anchor.id),
+        links: anchor.links.clone(),
+        metadata: anchor.metadata.clone(),
+        rust_version: rust_versions.get(&anchor.id.repr).cloned(),
+        unknown: unknown_fields(&unknowns, anchor, None),
     }];
+    // The resolved graph only includes optional dependencies whose features are enabled.
+    let node = metadata
+        .resolve
+        .as_ref()
+        .and_then(|resolve| resolve.nodes.iter().find(|node| node.id == anchor.id));
+
     for dependency in &anchor.dependencies {
-        let position = match metadata.packages.iter().position(|x| x.name == dependency.name) {
-            Some(x) => x,
-            _ => continue, // optional dep
+        let package = match node {
+            Some(node) => match resolve_dependency(&metadata, anchor, node, dependency) {
+                Some(x) => x,
+                _ => continue, // optional dep
+            },
+            None => match metadata.packages.iter().find(|x| x.name == dependency.name) {
+                Some(x) => x,
+                _ => continue, // optional dep
+            },
         };
-        let package = &metadata.packages[position];
         let applicable = match &dependency.target {
-            Some(target) => {
-                let default_target = crate::metadata::default_target()?;
-                target_spec::eval(&target.to_string(), &std::env::var("TARGET").unwrap_or(default_target))
-                    == Ok(Some(true))
-            }
+            // Use the same target that the metadata was filtered for.
+            Some(target) => target_spec::eval(&target.to_string(), &crate::metadata::get_target()) == Ok(Some(true)),
             _ => true,
         };
         if !applicable {
@@ -51,22 +115,324 @@ pub fn get_deps(anchor_name: &str) -> Result<Vec<DepInfo>, Error> {
         }
         deps.push(DepInfo {
             name: dependency.name.clone(),
+            rename: dependency.rename.clone(),
             version: package.version.to_string(),
             source: dependency.source.clone(),
+            resolved_source: package.source.as_ref().map(|source| source.to_string()),
+            manifest_path: package.manifest_path.clone(),
+            kind: Some(dependency.kind),
+            features: resolved_features(&metadata, &package.id),
+            links: package.links.clone(),
+            metadata: package.metadata.clone(),
+            rust_version: rust_versions.get(&package.id.repr).cloned(),
+            unknown: unknown_fields(&unknowns, package, Some((anchor, Some(dependency)))),
         });
     }
     Ok(deps)
 }
 
-pub fn get_dep(anchor_name: &str, dep_name: &str) -> Result<DepInfo, Error> {
-    for dep in get_deps(anchor_name)? {
-        if dep.name == dep_name {
-            return Ok(dep);
+/// Walks the resolved graph from each of the anchor's direct dependencies,
+/// following only normal dependencies after that, so build dependencies and
+/// dev-dependencies of other crates are excluded. The graph already reflects
+/// the target platform and enabled features.
+fn find_transitive_deps(anchor_name: &str) -> Result<Vec<DepInfo>, Error> {
+    let metadata = crate::metadata::get_metadata()?;
+    let rust_versions = crate::metadata::get_rust_versions()?;
+    let unknowns = crate::metadata::get_unknowns()?;
+    let anchor = find_anchor(&metadata, anchor_name)?;
+    let resolve = metadata
+        .resolve
+        .as_ref()
+        .ok_or_else(|| anyhow!("cargo metadata did not include the resolved dependency graph"))?;
+    let node = |id: &PackageId| resolve.nodes.iter().find(|node| node.id == *id);
+
+    let mut deps = vec![DepInfo {
+        name: anchor.name.to_owned(),
+        rename: None,
+        version: anchor.version.to_string(),
+        source: anchor.source.as_ref().map(|source| source.to_string()),
+        resolved_source: anchor.source.as_ref().map(|source| source.to_string()),
+        manifest_path: anchor.manifest_path.clone(),
+        kind: None,
+        features: resolved_features(&metadata, &anchor.id),
+        links: anchor.links.clone(),
+        metadata: anchor.metadata.clone(),
+        rust_version: rust_versions.get(&anchor.id.repr).cloned(),
+        unknown: unknown_fields(&unknowns, anchor, None),
+    }];
+    let roots = node(&anchor.id).map(|node| node.deps.as_slice()).unwrap_or_default();
+    for kind in &[
+        DependencyKind::Normal,
+        DependencyKind::Development,
+        DependencyKind::Build,
+    ] {
+        let mut seen = HashSet::new();
+        // Along with each edge, this tracks whether the parent might not be linked.
+        let mut queue: VecDeque<(&Package, &NodeDep, bool)> = roots
+            .iter()
+            .filter(|dep| has_kind(dep, *kind))
+            .map(|dep| (anchor, dep, false))
+            .collect();
+        while let Some((parent, edge, uncertain)) = queue.pop_front() {
+            if !seen.insert(&edge.pkg) {
+                continue;
+            }
+            let package = &metadata[&edge.pkg];
+            let declared = declaration(parent, edge, package);
+            let mut unknown = unknown_fields(&unknowns, package, Some((parent, declared)));
+            if uncertain && !unknown.iter().any(|x| x == "linked") {
+                unknown.push("linked".to_string());
+            }
+            let uncertain = unknown.iter().any(|x| x == "linked");
+            deps.push(DepInfo {
+                name: package.name.clone(),
+                // Only the anchor's own names for its dependencies are meaningful here.
+                rename: match declared {
+                    Some(dependency) if parent.id == anchor.id => dependency.rename.clone(),
+                    _ => None,
+                },
+                version: package.version.to_string(),
+                source: match declared {
+                    Some(dependency) => dependency.source.clone(),
+                    None => package.source.as_ref().map(|source| source.to_string()),
+                },
+                resolved_source: package.source.as_ref().map(|source| source.to_string()),
+                manifest_path: package.manifest_path.clone(),
+                kind: Some(*kind),
+                features: resolved_features(&metadata, &package.id),
+                links: package.links.clone(),
+                metadata: package.metadata.clone(),
+                rust_version: rust_versions.get(&package.id.repr).cloned(),
+                unknown,
+            });
+            if let Some(node) = node(&package.id) {
+                queue.extend(
+                    node.deps
+                        .iter()
+                        .filter(|dep| has_kind(dep, DependencyKind::Normal))
+                        .map(|dep| (package, dep, uncertain)),
+                );
+            }
+        }
+    }
+    Ok(deps)
+}
+
+/// Finds how `parent` declares one of its dependencies in its manifest.
+fn declaration<'a>(parent: &'a Package, edge: &NodeDep, package: &Package) -> Option<&'a Dependency> {
+    // The resolved graph refers to renamed dependencies by their new name.
+    parent
+        .dependencies
+        .iter()
+        .find(|x| x.name == package.name && x.rename.iter().all(|rename| rename.replace('-', "_") == edge.name))
+}
+
+/// Lists what the fallback for `cargo metadata` couldn't determine about a package,
+/// given the `parent` that declares it, if any. See [`DepInfo::unknown`].
+fn unknown_fields(
+    unknowns: &Unknowns,
+    package: &Package,
+    parent: Option<(&Package, Option<&Dependency>)>,
+) -> Vec<String> {
+    let of = |package: &Package| unknowns.get(&package.id.repr).cloned().unwrap_or_default();
+    // A package's own `dependencies` only matter for the packages that it declares.
+    let mut fields: Vec<String> = of(package)
+        .into_iter()
+        .filter(|x| x != "dependencies" && x != "optional")
+        .collect();
+    if let Some((parent, declared)) = parent {
+        let parent_fields = of(parent);
+        let has = |field: &str| parent_fields.iter().any(|x| x == field);
+        if has("dependencies") {
+            fields.push("linked".to_string());
+            fields.push("source".to_string());
+        } else if has("optional") && declared.map_or(false, |x| x.optional) {
+            fields.push("linked".to_string());
+        }
+    }
+    fields
+}
+
+/// Fails if the fallback for `cargo metadata` couldn't read how the anchor declares its dependencies.
+fn check_declarations(unknowns: &Unknowns, anchor: &Package) -> Result<(), Error> {
+    let fields = unknowns.get(&anchor.id.repr).map(Vec::as_slice).unwrap_or_default();
+    if fields.iter().any(|x| x == "dependencies") {
+        return Err(anyhow!(
+            "unable to determine the dependencies of '{}' without `cargo metadata`, \
+             since only Cargo.lock and the local Cargo.toml files are read instead",
+            anchor.name
+        ));
+    }
+    Ok(())
+}
+
+/// Lists the features that are enabled on a package in the resolved graph.
+fn resolved_features(metadata: &Metadata, id: &PackageId) -> Vec<String> {
+    metadata
+        .resolve
+        .as_ref()
+        .and_then(|resolve| resolve.nodes.iter().find(|node| node.id == *id))
+        .map(|node| node.features.clone())
+        .unwrap_or_default()
+}
+
+/// Checks if an edge of the resolved graph is a certain kind of dependency.
+/// Cargo versions before 1.41 don't report the kinds, so every edge counts as normal then.
+fn has_kind(dep: &NodeDep, kind: DependencyKind) -> bool {
+    if dep.dep_kinds.is_empty() {
+        return kind == DependencyKind::Normal;
+    }
+    dep.dep_kinds.iter().any(|x| x.kind == kind)
+}
+
+/// The kinds of the anchor's dependencies that are linked into the current compilation.
+/// If the anchor is the crate being compiled, then this depends on the target:
+/// build scripts only get build dependencies, while tests, examples, and benchmarks
+/// also get dev-dependencies. Otherwise, only normal dependencies are linked.
+pub fn linked_kinds(anchor_name: &str) -> Result<Vec<DependencyKind>, Error> {
+    let metadata = crate::metadata::get_metadata()?;
+    let anchor = find_anchor(&metadata, anchor_name)?;
+    let manifest_dir = crate::metadata::get_manifest_dir()?;
+    if anchor.manifest_path.parent() != Some(Path::new(&manifest_dir)) {
+        return Ok(vec![DependencyKind::Normal]);
+    }
+
+    let crate_name = crate::metadata::get_crate_name().unwrap_or_default();
+    if crate_name.starts_with("build_script_") {
+        return Ok(vec![DependencyKind::Build]);
+    }
+    let dev_target = anchor.targets.iter().any(|target| {
+        target.name.replace('-', "_") == crate_name
+            && target
+                .kind
+                .iter()
+                .any(|kind| kind == "example" || kind == "test" || kind == "bench")
+    });
+    if dev_target || crate::metadata::is_test() {
+        Ok(vec![DependencyKind::Normal, DependencyKind::Development])
+    } else {
+        Ok(vec![DependencyKind::Normal])
+    }
+}
+
+/// Finds the package that a dependency from the anchor's manifest actually resolved to.
+/// This goes by package ID rather than by name, since the dependency graph may
+/// contain multiple versions of the same crate.
+fn resolve_dependency<'a>(
+    metadata: &'a Metadata,
+    anchor: &Package,
+    node: &Node,
+    dependency: &Dependency,
+) -> Option<&'a Package> {
+    // The resolved graph refers to renamed dependencies by their new name.
+    let lib_name = |name: &str| name.replace('-', "_");
+    let renames: Vec<String> = anchor
+        .dependencies
+        .iter()
+        .filter_map(|x| x.rename.as_deref().map(lib_name))
+        .collect();
+    let candidates: Vec<&Package> = node
+        .deps
+        .iter()
+        .map(|dep| (dep, &metadata[&dep.pkg]))
+        .filter(|(dep, package)| {
+            package.name == dependency.name
+                && match &dependency.rename {
+                    Some(rename) => dep.name == lib_name(rename),
+                    None => !renames.contains(&dep.name),
+                }
+        })
+        .map(|(_, package)| package)
+        .collect();
+
+    // If the same crate appears more than once, the version requirement tells them apart.
+    candidates
+        .iter()
+        .find(|package| dependency.req.matches(&package.version))
+        .or_else(|| candidates.first())
+        .copied()
+}
+
+/// Lists the names of all dependencies declared in the anchor's manifest,
+/// regardless of whether they're optional or target-specific.
+/// This includes both the package name and the local name of renamed dependencies.
+/// If `transitive`, then this lists every package that Cargo knows about instead.
+pub fn get_declared_deps(anchor_name: &str, transitive: bool) -> Result<Vec<String>, Error> {
+    let metadata = crate::metadata::get_metadata()?;
+    let anchor = find_anchor(&metadata, anchor_name)?;
+    if transitive {
+        return Ok(metadata.packages.iter().map(|x| x.name.clone()).collect());
+    }
+    let unknowns = crate::metadata::get_unknowns()?;
+    check_declarations(&unknowns, anchor)?;
+    Ok(anchor
+        .dependencies
+        .iter()
+        .flat_map(|x| std::iter::once(x.name.clone()).chain(x.rename.clone()))
+        .collect())
+}
+
+/// Lists the kinds of dependency that the anchor's manifest declares `name` as,
+/// going by either its package name or its local name. Unlike [`get_deps`], this
+/// includes optional dependencies that aren't enabled and dependencies for other targets.
+pub fn get_declared_kinds(anchor_name: &str, name: &str) -> Result<Vec<DependencyKind>, Error> {
+    let metadata = crate::metadata::get_metadata()?;
+    let anchor = find_anchor(&metadata, anchor_name)?;
+    let unknowns = crate::metadata::get_unknowns()?;
+    check_declarations(&unknowns, anchor)?;
+    Ok(anchor
+        .dependencies
+        .iter()
+        .filter(|x| x.name == name || x.rename.as_deref() == Some(name))
+        .map(|x| x.kind)
+        .collect())
+}
+
+/// Finds the anchor package. If several packages have the same name (e.g., when a
+/// workspace member is also vendored or patched), this prefers the package being
+/// compiled, and then the workspace members.
+fn find_anchor<'a>(metadata: &'a Metadata, anchor_name: &str) -> Result<&'a Package, Error> {
+    let candidates: Vec<&Package> = metadata.packages.iter().filter(|x| x.name == anchor_name).collect();
+    match candidates.len() {
+        0 => {
+            let members = metadata.workspace_members.iter().map(|id| metadata[id].name.as_str());
+            Err(match crate::diagnostic::closest(anchor_name, members) {
+                Some(suggestion) => anyhow!(
+                    "Cannot find metadata for the anchor package '{}' (did you mean '{}'?)",
+                    anchor_name,
+                    suggestion
+                ),
+                None => anyhow!("Cannot find metadata for the anchor package '{}'", anchor_name),
+            })
+        }
+        1 => Ok(candidates[0]),
+        _ => {
+            let manifest_dir = crate::metadata::get_manifest_dir()?;
+            if let Some(current) = candidates
+                .iter()
+                .find(|x| x.manifest_path.parent() == Some(Path::new(&manifest_dir)))
+            {
+                return Ok(current);
+            }
+
+            let members: Vec<&Package> = candidates
+                .iter()
+                .filter(|x| metadata.workspace_members.contains(&x.id))
+                .copied()
+                .collect();
+            if members.len() == 1 {
+                return Ok(members[0]);
+            }
+
+            Err(anyhow!(
+                "The anchor package '{}' is ambiguous, since it could refer to any of: {}",
+                anchor_name,
+                candidates
+                    .iter()
+                    .map(|x| x.id.repr.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
         }
     }
-    Err(anyhow!(
-        "Unable to find information for dependency '{}' of '{}'",
-        dep_name,
-        anchor_name
-    ))
 }
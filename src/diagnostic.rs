@@ -0,0 +1,153 @@
+//! Warnings and suggestions for the user.
+//!
+//! Stable Rust doesn't let proc macros emit warnings directly, so warnings are
+//! collected while the condition is parsed and evaluated, then emitted as uses
+//! of a `#[deprecated]` item with the message as its note.
+
+use proc_macro2::{Span, TokenStream};
+use quote::{quote, quote_spanned};
+use std::cell::RefCell;
+use syn::{Item, ItemFn, Stmt};
+
+thread_local! {
+    static WARNINGS: RefCell<Vec<(Span, String)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Queues a warning to be emitted along with the current macro's output.
+pub fn warn(span: Span, message: impl Into<String>) {
+    WARNINGS.with(|warnings| warnings.borrow_mut().push((span, message.into())));
+}
+
+/// Adds any queued warnings to the macro output.
+///
+/// `original` is the item that the attribute was applied to. It's used to
+/// decide where the warnings can go: inside the body of a function, or as
+/// a separate item next to anything that can only appear in a module.
+/// When neither is possible (e.g., for a function that was removed, which
+/// might have been inside of an `impl` block), emitting them as code could
+/// break the build, so they're just printed without any location instead.
+pub fn attach(output: TokenStream, original: &TokenStream) -> TokenStream {
+    let queued: Vec<_> = WARNINGS.with(|warnings| warnings.borrow_mut().drain(..).collect());
+    if queued.is_empty() {
+        return output;
+    }
+    let warnings: Vec<TokenStream> = queued
+        .iter()
+        .map(|&(span, ref message)| {
+            quote_spanned! {span=>
+                {
+                    #[deprecated(note = #message)]
+                    struct RealiaWarning;
+                    let _ = RealiaWarning;
+                }
+            }
+        })
+        .collect();
+
+    if !output.is_empty() {
+        if let Ok(mut item) = syn::parse2::<ItemFn>(output.clone()) {
+            for warning in warnings.into_iter().rev() {
+                let stmt: Stmt = syn::parse_quote!(#warning);
+                item.block.stmts.insert(0, stmt);
+            }
+            return quote!(#item);
+        }
+    }
+
+    match syn::parse2::<Item>(original.clone()) {
+        Ok(Item::Enum(_))
+        | Ok(Item::ExternCrate(_))
+        | Ok(Item::ForeignMod(_))
+        | Ok(Item::Impl(_))
+        | Ok(Item::Mod(_))
+        | Ok(Item::Static(_))
+        | Ok(Item::Struct(_))
+        | Ok(Item::Trait(_))
+        | Ok(Item::TraitAlias(_))
+        | Ok(Item::Union(_))
+        | Ok(Item::Use(_)) => quote! {
+            #output
+            #(const _: () = #warnings;)*
+        },
+        _ => {
+            for (_, message) in queued {
+                eprintln!("warning: {}", message);
+            }
+            output
+        }
+    }
+}
+
+/// Discards any queued warnings, e.g. when the macro fails with an error.
+pub fn clear() {
+    WARNINGS.with(|warnings| warnings.borrow_mut().clear());
+}
+
+/// Finds the candidate most similar to `name`, if any is reasonably close.
+/// Hyphens and underscores are considered equivalent, like in crate names.
+pub fn closest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let name = normalize(name);
+    let threshold = std::cmp::max(name.len() / 3, 1);
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(&name, &normalize(candidate)), candidate))
+        .filter(|(distance, _)| *distance <= threshold)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Checks if two crate names are equivalent, treating hyphens and underscores the same.
+pub fn same_crate(a: &str, b: &str) -> bool {
+    normalize(a) == normalize(b)
+}
+
+fn normalize(name: &str) -> String {
+    name.replace('-', "_")
+}
+
+/// Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("syn", ""), 3);
+        assert_eq!(edit_distance("", "syn"), 3);
+        assert_eq!(edit_distance("serde", "serde"), 0);
+        assert_eq!(edit_distance("serde", "sedre"), 2);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("héllo", "hello"), 1);
+    }
+
+    #[test]
+    fn closest_candidates() {
+        let candidates = ["serde", "serde_json", "syn", "realia-tests"];
+
+        assert_eq!(closest("serd", candidates.iter().copied()), Some("serde"));
+        assert_eq!(closest("serde-json", candidates.iter().copied()), Some("serde_json"));
+        assert_eq!(closest("realia_test", candidates.iter().copied()), Some("realia-tests"));
+        // Short names still allow one typo.
+        assert_eq!(closest("sym", candidates.iter().copied()), Some("syn"));
+        // Ties go to the first candidate.
+        assert_eq!(closest("ab", vec!["aa", "bb"]), Some("aa"));
+        // Nothing is suggested when every candidate is too different.
+        assert_eq!(closest("tokio", candidates.iter().copied()), None);
+        assert_eq!(closest("syn", Vec::new()), None);
+    }
+}
@@ -1,86 +1,648 @@
-use crate::dep::{get_dep, get_deps};
+use crate::{
+    dep::{get_declared_deps, get_declared_kinds, get_deps, linked_kinds, DepInfo},
+    diagnostic::{closest, same_crate, warn},
+    metadata::rustc_version,
+    source::{is_patched, is_vendored, registry_indices, registry_names, Expected, Source, SourceArg},
+    version::{parse_version, Requirement, Version},
+};
+use cargo_metadata::DependencyKind;
+use proc_macro2::Span;
+use std::{cmp::Ordering, sync::Arc};
 use syn::{
     parenthesized,
     parse::{Parse, ParseStream, Result},
     punctuated::Punctuated,
-    LitStr, Token,
+    Error, Ident, Lit, LitStr, Token,
 };
-use version_compare::{CompOp, VersionCompare};
 
+/// A parsed condition.
+///
+/// Arguments are kept as the original literals rather than plain strings,
+/// so that any diagnostics can point at the exact argument that caused them.
 pub enum Expr {
     Not(Box<Expr>),
     Any(Vec<Expr>),
     All(Vec<Expr>),
     EnvExists {
-        name: String,
+        name: LitStr,
     },
     EnvEquals {
-        name: String,
-        value: String,
+        name: LitStr,
+        value: LitStr,
     },
     CmdExists {
-        name: String,
+        name: LitStr,
     },
     DepExists {
-        anchor: String,
-        name: String,
+        /// Either `dep` or `dep_transitive`, whichever was written.
+        keyword: &'static str,
+        anchor: LitStr,
+        name: LitStr,
+        /// Whether to check the whole dependency closure instead of only direct dependencies.
+        transitive: bool,
     },
     DepEquals {
-        anchor: String,
-        name: String,
-        version: String,
+        /// Either `dep` or `dep_transitive`, whichever was written.
+        keyword: &'static str,
+        anchor: LitStr,
+        name: LitStr,
+        version: Version,
+        /// Whether to check the whole dependency closure instead of only direct dependencies.
+        transitive: bool,
+        /// Which of the dependency's copies must pass the check.
+        copies: Copies,
     },
     DepSince {
-        anchor: String,
-        name: String,
-        version: String,
+        anchor: LitStr,
+        name: LitStr,
+        version: Version,
+        /// Whether a pre-release of the dependency can satisfy the check.
+        prerelease: bool,
+        /// Whether to check the whole dependency closure instead of only direct dependencies.
+        transitive: bool,
+        /// Which of the dependency's copies must pass the check.
+        copies: Copies,
     },
     DepBefore {
-        anchor: String,
-        name: String,
-        version: String,
+        anchor: LitStr,
+        name: LitStr,
+        version: Version,
+        /// Whether a pre-release of the dependency can satisfy the check.
+        prerelease: bool,
+        /// Whether to check the whole dependency closure instead of only direct dependencies.
+        transitive: bool,
+        /// Which of the dependency's copies must pass the check.
+        copies: Copies,
     },
     DepFromRegistry {
-        anchor: String,
-        name: String,
+        anchor: LitStr,
+        name: LitStr,
+    },
+    DepRenamed {
+        anchor: LitStr,
+        name: LitStr,
+    },
+    DepMatches {
+        anchor: LitStr,
+        name: LitStr,
+        requirement: Requirement,
+        /// Whether to check the whole dependency closure instead of only direct dependencies.
+        transitive: bool,
+        /// Which of the dependency's copies must pass the check.
+        copies: Copies,
+    },
+    DepDuplicated {
+        anchor: LitStr,
+        name: LitStr,
+    },
+    DepKind {
+        anchor: LitStr,
+        name: LitStr,
+        kind: DependencyKind,
+    },
+    DepFeature {
+        anchor: LitStr,
+        name: LitStr,
+        feature: LitStr,
+        /// Whether to check the whole dependency closure instead of only direct dependencies.
+        transitive: bool,
+    },
+    DepSource {
+        anchor: LitStr,
+        name: LitStr,
+        source: SourceArg,
+        /// Whether to check the whole dependency closure instead of only direct dependencies.
+        transitive: bool,
+    },
+    DepGit {
+        anchor: LitStr,
+        name: LitStr,
+        rev: Option<LitStr>,
+        branch: Option<LitStr>,
+        tag: Option<LitStr>,
+        /// Whether to check the whole dependency closure instead of only direct dependencies.
+        transitive: bool,
+    },
+    DepPatched {
+        anchor: LitStr,
+        name: LitStr,
+        /// Whether to check the whole dependency closure instead of only direct dependencies.
+        transitive: bool,
+    },
+    DepLinks {
+        anchor: LitStr,
+        links: LitStr,
+    },
+    DepMetadata {
+        anchor: LitStr,
+        name: LitStr,
+        /// A dotted path into `[package.metadata]`, like `foo.bar`.
+        path: LitStr,
+        /// The expected value, or `None` to check if the path exists.
+        value: Option<Lit>,
+        /// Whether to check the whole dependency closure instead of only direct dependencies.
+        transitive: bool,
+    },
+    DepMsrvSince {
+        anchor: LitStr,
+        name: LitStr,
+        version: Version,
+        /// Whether to check the whole dependency closure instead of only direct dependencies.
+        transitive: bool,
+    },
+    DepMsrvBefore {
+        anchor: LitStr,
+        name: LitStr,
+        version: Version,
+        /// Whether to check the whole dependency closure instead of only direct dependencies.
+        transitive: bool,
+    },
+    DepMsrvSupported {
+        anchor: LitStr,
+        name: LitStr,
+        /// Whether to check the whole dependency closure instead of only direct dependencies.
+        transitive: bool,
     },
 }
 
+/// How to treat a dependency that appears at several versions,
+/// which is mostly relevant for transitive checks.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Copies {
+    /// At least one version passes.
+    Any,
+    /// There is at least one version, and all of them pass.
+    All,
+    /// Exactly one version passes.
+    One,
+}
+
 impl Expr {
-    pub fn eval(&self) -> bool {
+    pub fn eval(&self) -> Result<bool> {
         use self::Expr::*;
 
         match self {
-            Not(expr) => !expr.eval(),
-            Any(exprs) => exprs.iter().any(|e| e.eval()),
-            All(exprs) => exprs.iter().all(|e| e.eval()),
-            EnvExists { name } => std::env::var(name).is_ok(),
-            EnvEquals { name, value } => match std::env::var(name) {
-                Ok(x) => &x == value,
-                _ => false,
+            Not(expr) => Ok(!expr.eval()?),
+            Any(exprs) => {
+                for expr in exprs {
+                    if expr.eval()? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            All(exprs) => {
+                for expr in exprs {
+                    if !expr.eval()? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            EnvExists { name } => Ok(std::env::var(name.value()).is_ok()),
+            EnvEquals { name, value } => match std::env::var(name.value()) {
+                Ok(x) => Ok(x == value.value()),
+                _ => Ok(false),
             },
-            CmdExists { name } => which::which(name).is_ok(),
-            DepExists { anchor, name } => get_dep(anchor, name).is_ok(),
-            DepEquals { anchor, name, version } => get_deps(anchor).unwrap().iter().any(|dep| {
-                &dep.name == name && VersionCompare::compare_to(&dep.version, version, &CompOp::Eq).unwrap()
+            CmdExists { name } => Ok(which::which(name.value()).is_ok()),
+            DepExists {
+                anchor,
+                name,
+                transitive,
+                ..
+            } => Ok(!self.matching(anchor, name, *transitive)?.is_empty()),
+            DepEquals {
+                anchor,
+                name,
+                version,
+                transitive,
+                copies,
+                ..
+            } => self.compare(anchor, name, *transitive, *copies, &version.lit, |x| {
+                x.cmp_precedence(&version.value) == Ordering::Equal
+                    && (version.value.build.is_empty() || x.build == version.value.build)
             }),
-            DepSince { anchor, name, version } => get_deps(anchor).unwrap().iter().any(|dep| {
-                &dep.name == name && VersionCompare::compare_to(&dep.version, version, &CompOp::Ge).unwrap()
+            DepSince {
+                anchor,
+                name,
+                version,
+                prerelease,
+                transitive,
+                copies,
+            } => self.compare(anchor, name, *transitive, *copies, &version.lit, |x| {
+                (*prerelease || x.pre.is_empty()) && x.cmp_precedence(&version.value) != Ordering::Less
             }),
-            DepBefore { anchor, name, version } => get_deps(anchor).unwrap().iter().any(|dep| {
-                &dep.name == name && VersionCompare::compare_to(&dep.version, version, &CompOp::Lt).unwrap()
+            DepBefore {
+                anchor,
+                name,
+                version,
+                prerelease,
+                transitive,
+                copies,
+            } => self.compare(anchor, name, *transitive, *copies, &version.lit, |x| {
+                (*prerelease || x.pre.is_empty()) && x.cmp_precedence(&version.value) == Ordering::Less
             }),
-            DepFromRegistry { anchor, name } => get_deps(anchor).unwrap().iter().any(|dep| {
-                &dep.name == name
-                    && match &dep.source {
-                        Some(source) => source.starts_with("registry+"),
+            DepFromRegistry { anchor, name } => Ok(self
+                .matching(anchor, name, false)?
+                .iter()
+                .any(|dep| matches!(Source::parse(dep.source.as_deref()), Source::Registry(_)))),
+            DepRenamed { anchor, name } => {
+                self.check_declared(anchor, name, false)?;
+                let renamed: Vec<DepInfo> = self
+                    .linked(anchor, false)?
+                    .into_iter()
+                    .filter(|dep| dep.rename.as_deref() == Some(&name.value()))
+                    .collect();
+                self.check_known(name, &renamed, "linked")?;
+                Ok(!renamed.is_empty())
+            }
+            DepMatches {
+                anchor,
+                name,
+                requirement,
+                transitive,
+                copies,
+            } => self.compare(anchor, name, *transitive, *copies, &requirement.lit, |x| {
+                requirement.value.matches(x)
+            }),
+            DepDuplicated { anchor, name } => {
+                let mut versions: Vec<String> = self
+                    .matching(anchor, name, true)?
+                    .into_iter()
+                    .map(|dep| dep.version)
+                    .collect();
+                versions.sort();
+                versions.dedup();
+                Ok(versions.len() > 1)
+            }
+            DepKind { anchor, name, kind } => {
+                self.check_declared(anchor, name, false)?;
+                let kinds = get_declared_kinds(&anchor.value(), &name.value())
+                    .map_err(|e| self.error(anchor, format!("{:#}", e)))?;
+                Ok(kinds.contains(kind))
+            }
+            DepFeature {
+                anchor,
+                name,
+                feature,
+                transitive,
+            } => {
+                let deps = self.matching(anchor, name, *transitive)?;
+                self.check_known(feature, &deps, "features")?;
+                Ok(deps.iter().any(|dep| dep.features.contains(&feature.value())))
+            }
+            DepSource {
+                anchor,
+                name,
+                source,
+                transitive,
+            } => {
+                let indices = match &source.value {
+                    Expected::Registry(Some(registry)) => self.registry(&source.lit, registry)?,
+                    _ => vec![],
+                };
+                let deps = self.matching(anchor, name, *transitive)?;
+                if let Expected::Vendored = source.value {
+                    self.check_known(&source.lit, &deps, "manifest_path")?;
+                }
+                Ok(deps.iter().any(|dep| {
+                    let actual = Source::parse(dep.resolved_source.as_deref());
+                    match (&source.value, &actual) {
+                        (Expected::Path, Source::Path) => true,
+                        (Expected::Git, Source::Git(_)) => true,
+                        (Expected::Registry(None), Source::Registry(_)) => true,
+                        (Expected::Registry(Some(_)), Source::Registry(index)) => indices.contains(index),
+                        (Expected::Vendored, _) => is_vendored(&actual, &dep.manifest_path),
                         _ => false,
                     }
+                }))
+            }
+            DepGit {
+                anchor,
+                name,
+                rev,
+                branch,
+                tag,
+                transitive,
+            } => Ok(self.matching(anchor, name, *transitive)?.iter().any(|dep| {
+                match Source::parse(dep.resolved_source.as_deref()) {
+                    Source::Git(git) => {
+                        rev.iter().all(|rev| git.is_rev(&rev.value()))
+                            && branch.iter().all(|branch| git.branch == Some(branch.value()))
+                            && tag.iter().all(|tag| git.tag == Some(tag.value()))
+                    }
+                    _ => false,
+                }
+            })),
+            DepPatched {
+                anchor,
+                name,
+                transitive,
+            } => {
+                let deps = self.matching(anchor, name, *transitive)?;
+                self.check_known(name, &deps, "source")?;
+                Ok(deps
+                    .iter()
+                    .any(|dep| is_patched(dep.source.as_deref(), dep.resolved_source.as_deref())))
+            }
+            DepLinks { anchor, links } => {
+                let deps = self.linked(anchor, true)?;
+                self.check_known(links, &deps, "linked")?;
+                self.check_known(links, &deps, "links")?;
+                Ok(deps.iter().any(|dep| dep.links.as_deref() == Some(&links.value())))
+            }
+            DepMetadata {
+                anchor,
+                name,
+                path,
+                value,
+                transitive,
+            } => {
+                let deps = self.matching(anchor, name, *transitive)?;
+                self.check_known(path, &deps, "metadata")?;
+                Ok(deps
+                    .iter()
+                    .any(|dep| match (lookup(&dep.metadata, &path.value()), value) {
+                        (Some(actual), Some(expected)) => metadata_equals(actual, expected),
+                        (Some(actual), None) => !actual.is_null(),
+                        (None, _) => false,
+                    }))
+            }
+            DepMsrvSince {
+                anchor,
+                name,
+                version,
+                transitive,
+            } => self.compare_msrv(anchor, name, *transitive, &version.lit, |msrv| {
+                msrv.iter().any(|msrv| release(msrv) >= release(&version.value))
+            }),
+            DepMsrvBefore {
+                anchor,
+                name,
+                version,
+                transitive,
+            } => self.compare_msrv(anchor, name, *transitive, &version.lit, |msrv| {
+                msrv.iter().any(|msrv| release(msrv) < release(&version.value))
             }),
+            DepMsrvSupported {
+                anchor,
+                name,
+                transitive,
+            } => {
+                let rustc = rustc_version()
+                    .map_err(|e| self.error(name, format!("{:#}", e)))
+                    .and_then(|x| {
+                        parse_version(&x)
+                            .ok_or_else(|| self.error(name, format!("unable to parse the rustc version '{}'", x)))
+                    })?;
+                self.compare_msrv(anchor, name, *transitive, name, |msrv| {
+                    msrv.iter().all(|msrv| release(msrv) <= release(&rustc))
+                })
+            }
+        }
+    }
+
+    /// The attribute name of this predicate, for use in error messages.
+    fn keyword(&self) -> &'static str {
+        use self::Expr::*;
+
+        match self {
+            Not(_) => "not",
+            Any(_) => "any",
+            All(_) => "all",
+            EnvExists { .. } | EnvEquals { .. } => "env",
+            CmdExists { .. } => "cmd",
+            DepExists { keyword, .. } | DepEquals { keyword, .. } => keyword,
+            DepSince { .. } => "dep_since",
+            DepBefore { .. } => "dep_before",
+            DepFromRegistry { .. } => "dep_from_registry",
+            DepRenamed { .. } => "dep_renamed",
+            DepMatches { .. } => "dep_matches",
+            DepDuplicated { .. } => "dep_duplicated",
+            DepKind { .. } => "dep_kind",
+            DepFeature { .. } => "dep_feature",
+            DepSource { .. } => "dep_source",
+            DepGit { .. } => "dep_git",
+            DepPatched { .. } => "dep_patched",
+            DepLinks { .. } => "dep_links",
+            DepMetadata { .. } => "dep_metadata",
+            DepMsrvSince { .. } => "dep_msrv_since",
+            DepMsrvBefore { .. } => "dep_msrv_before",
+            DepMsrvSupported { .. } => "dep_msrv_supported",
+        }
+    }
+
+    /// Creates an error that points at the argument responsible for it.
+    fn error(&self, arg: &LitStr, message: impl std::fmt::Display) -> Error {
+        Error::new(arg.span(), format!("`{}` check failed: {}", self.keyword(), message))
+    }
+
+    fn deps(&self, anchor: &LitStr, transitive: bool) -> Result<Arc<Vec<DepInfo>>> {
+        get_deps(&anchor.value(), transitive).map_err(|e| self.error(anchor, format!("{:#}", e)))
+    }
+
+    /// Finds the index URLs of a registry configured for Cargo, by name.
+    fn registry(&self, arg: &LitStr, name: &str) -> Result<Vec<String>> {
+        let indices = registry_indices(name).map_err(|e| self.error(arg, format!("{:#}", e)))?;
+        if let Some(indices) = indices {
+            return Ok(indices);
+        }
+
+        let names = registry_names().map_err(|e| self.error(arg, format!("{:#}", e)))?;
+        Err(self.error(
+            arg,
+            match closest(name, names.iter().map(String::as_str)) {
+                Some(suggestion) => format!(
+                    "unknown registry '{}' (did you mean '{}'?); registries are configured \
+                     in `.cargo/config.toml` or with `CARGO_REGISTRIES_<NAME>_INDEX`",
+                    name, suggestion
+                ),
+                None => format!(
+                    "unknown registry '{}'; registries are configured \
+                     in `.cargo/config.toml` or with `CARGO_REGISTRIES_<NAME>_INDEX`",
+                    name
+                ),
+            },
+        ))
+    }
+
+    /// Lists the anchor's dependencies that are linked into the current compilation,
+    /// so that e.g. dev-dependencies are excluded when building a library.
+    fn linked(&self, anchor: &LitStr, transitive: bool) -> Result<Vec<DepInfo>> {
+        let kinds = linked_kinds(&anchor.value()).map_err(|e| self.error(anchor, format!("{:#}", e)))?;
+        Ok(self
+            .deps(anchor, transitive)?
+            .iter()
+            .filter(|dep| dep.kind.iter().all(|kind| kinds.contains(kind)))
+            .cloned()
+            .collect())
+    }
+
+    /// Finds the anchor's linked dependencies called `name`,
+    /// either by their package name or by their local name if renamed.
+    fn matching(&self, anchor: &LitStr, name: &LitStr, transitive: bool) -> Result<Vec<DepInfo>> {
+        self.check_declared(anchor, name, transitive)?;
+        let deps: Vec<DepInfo> = self
+            .linked(anchor, transitive)?
+            .into_iter()
+            .filter(|dep| dep.name == name.value() || dep.rename.as_ref() == Some(&name.value()))
+            .collect();
+        self.check_known(name, &deps, "linked")?;
+        Ok(deps)
+    }
+
+    /// Reports when the fallback for `cargo metadata` couldn't determine `field` of any
+    /// of `deps`, rather than guessing at it. See [`DepInfo::unknown`].
+    fn check_known(&self, arg: &LitStr, deps: &[DepInfo], field: &str) -> Result<()> {
+        let dep = match deps.iter().find(|dep| dep.unknown.iter().any(|x| x == field)) {
+            Some(dep) => dep,
+            None => return Ok(()),
+        };
+        let message = match field {
+            "linked" => format!("unable to determine whether '{}' is used", dep.name),
+            _ => format!("unable to determine the `{}` of '{}'", field, dep.name),
+        };
+        Err(self.error(
+            arg,
+            format!(
+                "{} without `cargo metadata`, since only Cargo.lock and the local Cargo.toml files are read instead",
+                message
+            ),
+        ))
+    }
+
+    /// In strict mode, reports dependency names that the anchor doesn't declare at all,
+    /// since that's most likely a typo. See [`Strict`] for how it's enabled.
+    /// For transitive checks, any package in the graph counts as declared.
+    fn check_declared(&self, anchor: &LitStr, name: &LitStr, transitive: bool) -> Result<()> {
+        let mode = Strict::parse(std::env::var("REALIA_STRICT").ok().as_deref())
+            .map_err(|message| self.error(name, message))?;
+        if mode == Strict::Off || name.value() == anchor.value() {
+            return Ok(());
+        }
+
+        let declared =
+            get_declared_deps(&anchor.value(), transitive).map_err(|e| self.error(anchor, format!("{:#}", e)))?;
+        if declared.iter().any(|x| *x == name.value()) {
+            return Ok(());
+        }
+
+        let message = match closest(&name.value(), declared.iter().map(String::as_str)) {
+            Some(suggestion) => format!(
+                "unknown dependency '{}' of '{}' (did you mean '{}'?)",
+                name.value(),
+                anchor.value(),
+                suggestion
+            ),
+            None => format!("unknown dependency '{}' of '{}'", name.value(), anchor.value()),
+        };
+        if mode == Strict::Warn {
+            warn(name.span(), format!("`{}` check: {}", self.keyword(), message));
+            Ok(())
+        } else {
+            Err(self.error(name, message))
+        }
+    }
+
+    /// Checks if any of the anchor's dependencies called `name` has a `rust-version`
+    /// that passes `test`, which gets `None` if the dependency doesn't declare one.
+    /// Any errors will point at `arg`.
+    fn compare_msrv(
+        &self,
+        anchor: &LitStr,
+        name: &LitStr,
+        transitive: bool,
+        arg: &LitStr,
+        test: impl Fn(Option<&semver::Version>) -> bool,
+    ) -> Result<bool> {
+        let deps = self.matching(anchor, name, transitive)?;
+        self.check_known(arg, &deps, "rust_version")?;
+        for dep in deps {
+            let msrv = match &dep.rust_version {
+                Some(raw) => match parse_version(raw) {
+                    Some(msrv) => Some(msrv),
+                    None => {
+                        return Err(self.error(arg, format!("unable to parse rust-version '{}' of '{}'", raw, dep.name)))
+                    }
+                },
+                None => None,
+            };
+            if test(msrv.as_ref()) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Checks if the anchor's dependencies called `name` have versions that pass `test`,
+    /// where `copies` decides how many of them need to. Any errors will point at `arg`.
+    fn compare(
+        &self,
+        anchor: &LitStr,
+        name: &LitStr,
+        transitive: bool,
+        copies: Copies,
+        arg: &LitStr,
+        test: impl Fn(&semver::Version) -> bool,
+    ) -> Result<bool> {
+        let mut versions = vec![];
+        for dep in self.matching(anchor, name, transitive)? {
+            match parse_version(&dep.version) {
+                Some(version) => versions.push(version),
+                None => {
+                    return Err(self.error(
+                        arg,
+                        format!("unable to parse version '{}' of '{}'", dep.version, dep.name),
+                    ))
+                }
+            }
+        }
+        // The same version may be listed more than once, e.g. as both
+        // a normal and a target-specific dependency.
+        versions.sort();
+        versions.dedup();
+
+        let passed = versions.iter().filter(|version| test(version)).count();
+        Ok(match copies {
+            Copies::Any => passed > 0,
+            Copies::All => passed > 0 && passed == versions.len(),
+            Copies::One => passed == 1,
+        })
+    }
+}
+
+/// How strict mode reports dependency names that the anchor doesn't declare,
+/// based on the `REALIA_STRICT` environment variable.
+#[derive(Debug, PartialEq, Eq)]
+enum Strict {
+    /// Unset, empty, `0`, or `false`.
+    Off,
+    /// `warn`.
+    Warn,
+    /// `1`, `true`, or `error`.
+    Error,
+}
+
+impl Strict {
+    fn parse(value: Option<&str>) -> std::result::Result<Self, String> {
+        match value.map(|x| x.trim().to_lowercase()).as_deref() {
+            None | Some("") | Some("0") | Some("false") => Ok(Strict::Off),
+            Some("warn") => Ok(Strict::Warn),
+            Some("1") | Some("true") | Some("error") => Ok(Strict::Error),
+            Some(_) => Err(format!(
+                "invalid value for REALIA_STRICT: '{}' (expected `warn`, `error`, `1`, `true`, `0`, or `false`)",
+                value.unwrap_or_default()
+            )),
         }
     }
 }
 
+/// Gets the release part of a version, ignoring any pre-release.
+/// Like Cargo, this treats a nightly compiler as if it were the corresponding release.
+fn release(version: &semver::Version) -> (u64, u64, u64) {
+    (version.major, version.minor, version.patch)
+}
+
 type Exprs = Punctuated<Expr, Token![,]>;
 
 mod keyword {
@@ -93,6 +655,20 @@ mod keyword {
     syn::custom_keyword!(dep_since);
     syn::custom_keyword!(dep_before);
     syn::custom_keyword!(dep_from_registry);
+    syn::custom_keyword!(dep_renamed);
+    syn::custom_keyword!(dep_matches);
+    syn::custom_keyword!(dep_transitive);
+    syn::custom_keyword!(dep_duplicated);
+    syn::custom_keyword!(dep_kind);
+    syn::custom_keyword!(dep_feature);
+    syn::custom_keyword!(dep_source);
+    syn::custom_keyword!(dep_git);
+    syn::custom_keyword!(dep_patched);
+    syn::custom_keyword!(dep_links);
+    syn::custom_keyword!(dep_metadata);
+    syn::custom_keyword!(dep_msrv_since);
+    syn::custom_keyword!(dep_msrv_before);
+    syn::custom_keyword!(dep_msrv_supported);
 }
 
 impl Parse for Expr {
@@ -116,6 +692,34 @@ impl Parse for Expr {
             Self::parse_dep_before(input)
         } else if lookahead.peek(keyword::dep_from_registry) {
             Self::parse_dep_from_registry(input)
+        } else if lookahead.peek(keyword::dep_renamed) {
+            Self::parse_dep_renamed(input)
+        } else if lookahead.peek(keyword::dep_matches) {
+            Self::parse_dep_matches(input)
+        } else if lookahead.peek(keyword::dep_transitive) {
+            Self::parse_dep_transitive(input)
+        } else if lookahead.peek(keyword::dep_duplicated) {
+            Self::parse_dep_duplicated(input)
+        } else if lookahead.peek(keyword::dep_kind) {
+            Self::parse_dep_kind(input)
+        } else if lookahead.peek(keyword::dep_feature) {
+            Self::parse_dep_feature(input)
+        } else if lookahead.peek(keyword::dep_source) {
+            Self::parse_dep_source(input)
+        } else if lookahead.peek(keyword::dep_git) {
+            Self::parse_dep_git(input)
+        } else if lookahead.peek(keyword::dep_patched) {
+            Self::parse_dep_patched(input)
+        } else if lookahead.peek(keyword::dep_links) {
+            Self::parse_dep_links(input)
+        } else if lookahead.peek(keyword::dep_metadata) {
+            Self::parse_dep_metadata(input)
+        } else if lookahead.peek(keyword::dep_msrv_since) {
+            Self::parse_dep_msrv_since(input)
+        } else if lookahead.peek(keyword::dep_msrv_before) {
+            Self::parse_dep_msrv_before(input)
+        } else if lookahead.peek(keyword::dep_msrv_supported) {
+            Self::parse_dep_msrv_supported(input)
         } else {
             Err(lookahead.error())
         }
@@ -164,11 +768,11 @@ impl Expr {
             Ok(_) => {
                 let arg2: LitStr = paren.parse()?;
                 Ok(Expr::EnvEquals {
-                    name: arg1.value(),
-                    value: arg2.value(),
+                    name: arg1,
+                    value: arg2,
                 })
             }
-            Err(_) => Ok(Expr::EnvExists { name: arg1.value() }),
+            Err(_) => Ok(Expr::EnvExists { name: arg1 }),
         }
     }
 
@@ -179,78 +783,491 @@ impl Expr {
         parenthesized!(paren in input);
         let arg1: LitStr = paren.parse()?;
 
-        Ok(Expr::CmdExists { name: arg1.value() })
+        Ok(Expr::CmdExists { name: arg1 })
     }
 
     fn parse_dep(input: ParseStream) -> Result<Self> {
         input.parse::<keyword::dep>()?;
 
-        let paren;
-        parenthesized!(paren in input);
-        let arg1: LitStr = paren.parse()?;
-        paren.parse::<Token![,]>()?;
-        let arg2: LitStr = paren.parse()?;
-        match paren.parse::<Token![,]>() {
-            Ok(_) => {
-                let arg3: LitStr = paren.parse()?;
-                Ok(Expr::DepEquals {
-                    anchor: arg1.value(),
-                    name: arg2.value(),
-                    version: arg3.value(),
-                })
-            }
-            Err(_) => Ok(Expr::DepExists {
-                anchor: arg1.value(),
-                name: arg2.value(),
-            }),
+        let mut args = parse_args(input)?;
+        let transitive = args.take_bool("transitive")?.unwrap_or(false);
+        Self::parse_dep_args("dep", args, transitive)
+    }
+
+    fn parse_dep_transitive(input: ParseStream) -> Result<Self> {
+        input.parse::<keyword::dep_transitive>()?;
+
+        Self::parse_dep_args("dep_transitive", parse_args(input)?, true)
+    }
+
+    /// Shared by `dep` and `dep_transitive`, which only differ in their default scope.
+    fn parse_dep_args(keyword: &'static str, mut args: Args, transitive: bool) -> Result<Self> {
+        if args.lits.len() == 3 {
+            let copies = args.take_copies()?;
+            let (anchor, name, mut rest) = args.split_dep_args(1)?;
+            Ok(Expr::DepEquals {
+                keyword,
+                anchor,
+                name,
+                version: Version::new(rest.remove(0))?,
+                transitive,
+                copies,
+            })
+        } else {
+            let (anchor, name, _) = args.split_dep_args(0)?;
+            Ok(Expr::DepExists {
+                keyword,
+                anchor,
+                name,
+                transitive,
+            })
         }
     }
 
     fn parse_dep_since(input: ParseStream) -> Result<Self> {
         input.parse::<keyword::dep_since>()?;
 
-        let paren;
-        parenthesized!(paren in input);
-        let arg1: LitStr = paren.parse()?;
-        paren.parse::<Token![,]>()?;
-        let arg2: LitStr = paren.parse()?;
-        paren.parse::<Token![,]>()?;
-        let arg3: LitStr = paren.parse()?;
+        let mut args = parse_args(input)?;
+        let prerelease = args.take_bool("prerelease")?.unwrap_or(true);
+        let transitive = args.take_bool("transitive")?.unwrap_or(false);
+        let copies = args.take_copies()?;
+        let (anchor, name, mut rest) = args.split_dep_args(1)?;
         Ok(Expr::DepSince {
-            anchor: arg1.value(),
-            name: arg2.value(),
-            version: arg3.value(),
+            anchor,
+            name,
+            version: Version::new(rest.remove(0))?,
+            prerelease,
+            transitive,
+            copies,
         })
     }
 
     fn parse_dep_before(input: ParseStream) -> Result<Self> {
         input.parse::<keyword::dep_before>()?;
 
-        let paren;
-        parenthesized!(paren in input);
-        let arg1: LitStr = paren.parse()?;
-        paren.parse::<Token![,]>()?;
-        let arg2: LitStr = paren.parse()?;
-        paren.parse::<Token![,]>()?;
-        let arg3: LitStr = paren.parse()?;
+        let mut args = parse_args(input)?;
+        let prerelease = args.take_bool("prerelease")?.unwrap_or(true);
+        let transitive = args.take_bool("transitive")?.unwrap_or(false);
+        let copies = args.take_copies()?;
+        let (anchor, name, mut rest) = args.split_dep_args(1)?;
         Ok(Expr::DepBefore {
-            anchor: arg1.value(),
-            name: arg2.value(),
-            version: arg3.value(),
+            anchor,
+            name,
+            version: Version::new(rest.remove(0))?,
+            prerelease,
+            transitive,
+            copies,
         })
     }
 
     fn parse_dep_from_registry(input: ParseStream) -> Result<Self> {
         input.parse::<keyword::dep_from_registry>()?;
 
-        let paren;
-        parenthesized!(paren in input);
-        let arg1: LitStr = paren.parse()?;
-        paren.parse::<Token![,]>()?;
-        let arg2: LitStr = paren.parse()?;
-        Ok(Expr::DepFromRegistry {
-            anchor: arg1.value(),
-            name: arg2.value(),
+        let (anchor, name, _) = parse_args(input)?.split_dep_args(0)?;
+        Ok(Expr::DepFromRegistry { anchor, name })
+    }
+
+    fn parse_dep_renamed(input: ParseStream) -> Result<Self> {
+        input.parse::<keyword::dep_renamed>()?;
+
+        let (anchor, name, _) = parse_args(input)?.split_dep_args(0)?;
+        Ok(Expr::DepRenamed { anchor, name })
+    }
+
+    fn parse_dep_matches(input: ParseStream) -> Result<Self> {
+        input.parse::<keyword::dep_matches>()?;
+
+        let mut args = parse_args(input)?;
+        let transitive = args.take_bool("transitive")?.unwrap_or(false);
+        let copies = args.take_copies()?;
+        let (anchor, name, mut rest) = args.split_dep_args(1)?;
+        Ok(Expr::DepMatches {
+            anchor,
+            name,
+            requirement: Requirement::new(rest.remove(0))?,
+            transitive,
+            copies,
         })
     }
+
+    fn parse_dep_duplicated(input: ParseStream) -> Result<Self> {
+        input.parse::<keyword::dep_duplicated>()?;
+
+        let (anchor, name, _) = parse_args(input)?.split_dep_args(0)?;
+        Ok(Expr::DepDuplicated { anchor, name })
+    }
+
+    fn parse_dep_kind(input: ParseStream) -> Result<Self> {
+        input.parse::<keyword::dep_kind>()?;
+
+        let (anchor, name, mut rest) = parse_args(input)?.split_dep_args(1)?;
+        Ok(Expr::DepKind {
+            anchor,
+            name,
+            kind: parse_kind(&rest.remove(0))?,
+        })
+    }
+
+    fn parse_dep_feature(input: ParseStream) -> Result<Self> {
+        input.parse::<keyword::dep_feature>()?;
+
+        let mut args = parse_args(input)?;
+        let transitive = args.take_bool("transitive")?.unwrap_or(false);
+        let (anchor, name, mut rest) = args.split_dep_args(1)?;
+        Ok(Expr::DepFeature {
+            anchor,
+            name,
+            feature: rest.remove(0),
+            transitive,
+        })
+    }
+
+    fn parse_dep_source(input: ParseStream) -> Result<Self> {
+        input.parse::<keyword::dep_source>()?;
+
+        let mut args = parse_args(input)?;
+        let transitive = args.take_bool("transitive")?.unwrap_or(false);
+        let (anchor, name, mut rest) = args.split_dep_args(1)?;
+        Ok(Expr::DepSource {
+            anchor,
+            name,
+            source: SourceArg::new(rest.remove(0))?,
+            transitive,
+        })
+    }
+
+    fn parse_dep_git(input: ParseStream) -> Result<Self> {
+        input.parse::<keyword::dep_git>()?;
+
+        let mut args = parse_args(input)?;
+        let rev = args.take_str("rev")?;
+        let branch = args.take_str("branch")?;
+        let tag = args.take_str("tag")?;
+        let transitive = args.take_bool("transitive")?.unwrap_or(false);
+        let (anchor, name, _) = args.split_dep_args(0)?;
+        Ok(Expr::DepGit {
+            anchor,
+            name,
+            rev,
+            branch,
+            tag,
+            transitive,
+        })
+    }
+
+    fn parse_dep_patched(input: ParseStream) -> Result<Self> {
+        input.parse::<keyword::dep_patched>()?;
+
+        let mut args = parse_args(input)?;
+        let transitive = args.take_bool("transitive")?.unwrap_or(false);
+        let (anchor, name, _) = args.split_dep_args(0)?;
+        Ok(Expr::DepPatched {
+            anchor,
+            name,
+            transitive,
+        })
+    }
+
+    fn parse_dep_links(input: ParseStream) -> Result<Self> {
+        input.parse::<keyword::dep_links>()?;
+
+        // This takes the name of a native library instead of a dependency,
+        // but the anchor works the same way.
+        let (anchor, links, _) = parse_args(input)?.split_dep_args(0)?;
+        Ok(Expr::DepLinks { anchor, links })
+    }
+
+    fn parse_dep_metadata(input: ParseStream) -> Result<Self> {
+        input.parse::<keyword::dep_metadata>()?;
+
+        let mut args = parse_args(input)?;
+        let transitive = args.take_bool("transitive")?.unwrap_or(false);
+        // Like with `dep`, a string value can only be given along with the anchor.
+        let value = match args.value.take() {
+            Some(value @ Lit::Int(_)) | Some(value @ Lit::Float(_)) | Some(value @ Lit::Bool(_)) => Some(value),
+            Some(other) => {
+                return Err(Error::new(
+                    other.span(),
+                    "expected the value to be a string, number, or boolean",
+                ))
+            }
+            None if args.lits.len() == 4 => args.lits.pop().map(Lit::Str),
+            None => None,
+        };
+        let (anchor, name, mut rest) = args.split_dep_args(1)?;
+        let path = rest.remove(0);
+        if name.value().contains('.') {
+            // Crate names can't contain dots, so this is really a path that got
+            // shifted over because the anchor was omitted before a string value.
+            return Err(Error::new(
+                name.span(),
+                format!(
+                    "expected a dependency name, but found the path '{}'; \
+                     the anchor must be given explicitly when the value is a string",
+                    name.value()
+                ),
+            ));
+        }
+        if path.value().split('.').any(str::is_empty) {
+            return Err(Error::new(
+                path.span(),
+                format!("expected a dotted path like foo.bar, but found '{}'", path.value()),
+            ));
+        }
+        Ok(Expr::DepMetadata {
+            anchor,
+            name,
+            path,
+            value,
+            transitive,
+        })
+    }
+
+    fn parse_dep_msrv_since(input: ParseStream) -> Result<Self> {
+        input.parse::<keyword::dep_msrv_since>()?;
+
+        let mut args = parse_args(input)?;
+        let transitive = args.take_bool("transitive")?.unwrap_or(false);
+        let (anchor, name, mut rest) = args.split_dep_args(1)?;
+        Ok(Expr::DepMsrvSince {
+            anchor,
+            name,
+            version: Version::new(rest.remove(0))?,
+            transitive,
+        })
+    }
+
+    fn parse_dep_msrv_before(input: ParseStream) -> Result<Self> {
+        input.parse::<keyword::dep_msrv_before>()?;
+
+        let mut args = parse_args(input)?;
+        let transitive = args.take_bool("transitive")?.unwrap_or(false);
+        let (anchor, name, mut rest) = args.split_dep_args(1)?;
+        Ok(Expr::DepMsrvBefore {
+            anchor,
+            name,
+            version: Version::new(rest.remove(0))?,
+            transitive,
+        })
+    }
+
+    fn parse_dep_msrv_supported(input: ParseStream) -> Result<Self> {
+        input.parse::<keyword::dep_msrv_supported>()?;
+
+        let mut args = parse_args(input)?;
+        let transitive = args.take_bool("transitive")?.unwrap_or(false);
+        let (anchor, name, _) = args.split_dep_args(0)?;
+        Ok(Expr::DepMsrvSupported {
+            anchor,
+            name,
+            transitive,
+        })
+    }
+}
+
+/// Parses a dependency kind as written in the `kind` field of `cargo metadata`.
+fn parse_kind(kind: &LitStr) -> Result<DependencyKind> {
+    match kind.value().as_str() {
+        "normal" => Ok(DependencyKind::Normal),
+        "dev" => Ok(DependencyKind::Development),
+        "build" => Ok(DependencyKind::Build),
+        other => Err(Error::new(
+            kind.span(),
+            format!("expected \"normal\", \"dev\", or \"build\", but found '{}'", other),
+        )),
+    }
+}
+
+/// Looks up a dotted path in `[package.metadata]`.
+/// Numeric segments can also index into arrays.
+fn lookup<'a>(metadata: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+    path.split('.').try_fold(metadata, |value, key| match value {
+        serde_json::Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+        _ => value.get(key),
+    })
+}
+
+/// Compares a value from `[package.metadata]` with a literal. A string literal
+/// also matches a number or boolean with the same text, so `"3"` matches `3`.
+fn metadata_equals(actual: &serde_json::Value, expected: &Lit) -> bool {
+    use serde_json::Value;
+
+    match (expected, actual) {
+        (Lit::Str(x), Value::String(y)) => x.value() == *y,
+        (Lit::Str(x), Value::Number(_)) | (Lit::Str(x), Value::Bool(_)) => {
+            // Not `x.value() == *actual`, since that only matches strings.
+            let text = actual.to_string();
+            x.value() == text
+        }
+        (Lit::Int(x), Value::Number(y)) => !y.is_f64() && x.base10_digits() == y.to_string(),
+        (Lit::Float(x), Value::Number(y)) => x.base10_parse::<f64>().ok() == y.as_f64(),
+        (Lit::Bool(x), Value::Bool(y)) => x.value == *y,
+        _ => false,
+    }
+}
+
+/// The arguments of a check: string literals, then possibly another kind of literal
+/// as the final value, and then optionally options like `key = value`.
+struct Args {
+    span: Span,
+    lits: Vec<LitStr>,
+    value: Option<Lit>,
+    options: Vec<(Ident, Lit)>,
+}
+
+/// Parses a parenthesized list of arguments.
+fn parse_args(input: ParseStream) -> Result<Args> {
+    let paren;
+    let parens = parenthesized!(paren in input);
+
+    let mut lits = vec![];
+    let mut value: Option<Lit> = None;
+    let mut options = vec![];
+    while !paren.is_empty() {
+        if paren.peek(Ident) && paren.peek2(Token![=]) {
+            let key: Ident = paren.parse()?;
+            paren.parse::<Token![=]>()?;
+            options.push((key, paren.parse()?));
+        } else if let Some(value) = &value {
+            return Err(Error::new(value.span(), "expected string literal"));
+        } else if options.is_empty() {
+            match paren.parse()? {
+                Lit::Str(lit) => lits.push(lit),
+                other => value = Some(other),
+            }
+        } else {
+            return Err(paren.error("expected options to come after all other arguments"));
+        }
+        if paren.is_empty() {
+            break;
+        }
+        paren.parse::<Token![,]>()?;
+    }
+
+    Ok(Args {
+        span: parens.span,
+        lits,
+        value,
+        options,
+    })
+}
+
+impl Args {
+    fn take_option(&mut self, key: &str) -> Option<Lit> {
+        let position = self.options.iter().position(|(ident, _)| ident == key)?;
+        Some(self.options.remove(position).1)
+    }
+
+    fn take_bool(&mut self, key: &str) -> Result<Option<bool>> {
+        match self.take_option(key) {
+            Some(Lit::Bool(value)) => Ok(Some(value.value)),
+            Some(other) => Err(Error::new(
+                other.span(),
+                format!("expected `{} = true` or `{} = false`", key, key),
+            )),
+            None => Ok(None),
+        }
+    }
+
+    fn take_str(&mut self, key: &str) -> Result<Option<LitStr>> {
+        match self.take_option(key) {
+            Some(Lit::Str(value)) if !value.value().is_empty() => Ok(Some(value)),
+            Some(other) => Err(Error::new(
+                other.span(),
+                format!("expected `{} = \"...\"` with a non-empty string", key),
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// Parses the `copies` option, which defaults to `Copies::Any`.
+    fn take_copies(&mut self) -> Result<Copies> {
+        match self.take_option("copies") {
+            Some(Lit::Str(value)) if value.value() == "any" => Ok(Copies::Any),
+            Some(Lit::Str(value)) if value.value() == "all" => Ok(Copies::All),
+            Some(Lit::Str(value)) if value.value() == "one" => Ok(Copies::One),
+            Some(other) => Err(Error::new(
+                other.span(),
+                "expected `copies = \"any\"`, `copies = \"all\"`, or `copies = \"one\"`",
+            )),
+            None => Ok(Copies::Any),
+        }
+    }
+
+    /// Splits the arguments of a dependency check into the anchor, the dependency name,
+    /// and `extra` more arguments. If the anchor was omitted, it defaults to the crate
+    /// being compiled, which is available to proc macros as `CARGO_PKG_NAME`.
+    ///
+    /// Any supported options must be taken before calling this,
+    /// since anything left over is reported as an error.
+    fn split_dep_args(self, extra: usize) -> Result<(LitStr, LitStr, Vec<LitStr>)> {
+        if let Some((key, _)) = self.options.first() {
+            return Err(Error::new(key.span(), format!("unknown option `{}`", key)));
+        }
+        if let Some(value) = &self.value {
+            return Err(Error::new(value.span(), "expected string literal"));
+        }
+
+        let mut args = self.lits;
+        if args.len() == extra + 1 {
+            let anchor = match std::env::var("CARGO_PKG_NAME") {
+                Ok(name) => LitStr::new(&name, args[0].span()),
+                Err(_) => {
+                    return Err(Error::new(
+                        args[0].span(),
+                        "unable to infer the anchor crate because CARGO_PKG_NAME is not set; \
+                         please specify the anchor explicitly",
+                    ))
+                }
+            };
+            args.insert(0, anchor);
+        } else if let (Some(anchor), Ok(current)) = (args.first(), std::env::var("CARGO_PKG_NAME")) {
+            if !same_crate(&anchor.value(), &current) {
+                warn(
+                    anchor.span(),
+                    format!(
+                        "the anchor '{}' does not match the crate being compiled ('{}'), \
+                         so this checks the dependencies of '{}' instead; \
+                         omit the anchor to use the current crate",
+                        anchor.value(),
+                        current,
+                        anchor.value()
+                    ),
+                );
+            }
+        }
+        if args.len() != extra + 2 {
+            return Err(Error::new(
+                self.span,
+                format!(
+                    "expected {} or {} arguments, but found {}",
+                    extra + 1,
+                    extra + 2,
+                    args.len()
+                ),
+            ));
+        }
+
+        let rest = args.split_off(2);
+        let name = args.pop().unwrap();
+        let anchor = args.pop().unwrap();
+        Ok((anchor, name, rest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strict_mode() {
+        for off in &[None, Some(""), Some("0"), Some("false"), Some("FALSE")] {
+            assert_eq!(Strict::parse(*off), Ok(Strict::Off));
+        }
+        assert_eq!(Strict::parse(Some("warn")), Ok(Strict::Warn));
+        for error in &["1", "true", "error", "Error"] {
+            assert_eq!(Strict::parse(Some(error)), Ok(Strict::Error));
+        }
+        assert!(Strict::parse(Some("yes")).unwrap_err().contains("'yes'"));
+    }
 }
@@ -1,20 +1,33 @@
 //! Realia provides attribute macros for conditional compilation,
 //! analogous to `#[cfg(...)]` and `#[cfg_attr(...)]`.
+//!
+//! # Which dependencies count
+//! The dependency checks account for target-specific dependencies and for optional
+//! dependencies, based on which features are enabled in the current compilation.
+//! They also only consider dependencies that are linked into the current compilation:
+//! dev-dependencies count for tests, examples, and benchmarks,
+//! while build dependencies only count for build scripts.
+//! The exception is [`macro@dep_kind`], which checks what the manifest declares.
 
 #![allow(clippy::needless_doctest_main)]
 
 extern crate proc_macro;
 
 mod attr;
+mod cache;
 mod dep;
+mod diagnostic;
 mod expr;
+mod lockfile;
 mod metadata;
+mod source;
+mod version;
 
 use crate::{attr::Then, expr::Expr};
 use proc_macro::TokenStream;
 use proc_macro2::{Ident, Span, TokenStream as TokenStream2};
 use quote::quote;
-use syn::{parse_macro_input, ItemFn, Result};
+use syn::{ItemFn, Result};
 
 /// Checks whether an environment variable is defined and optionally
 /// what value it has.
@@ -74,14 +87,19 @@ pub fn cmd(args: TokenStream, input: TokenStream) -> TokenStream {
 /// Checks whether a crate has a certain dependency and optionally if that
 /// dependency is a certain version.
 ///
+/// Versions are compared according to semver precedence, so build metadata
+/// (like `+foo` in `1.2.3+foo`) is ignored unless you include it in the version
+/// you specify, in which case it must match exactly.
+///
 /// The first argument is an "anchor crate", which should generally be your crate.
 /// Since this functionality is implemented by checking the output of
 /// `cargo metadata --manifest-path $CARGO_MANIFEST_DIR/Cargo.toml`,
 /// the anchor is used to ensure consistent results when `$CARGO_MANIFEST_DIR`
 /// changes (e.g., when building your crate directly vs when building a downstream crate).
+/// If you omit the anchor, then it defaults to the crate being compiled.
 ///
-/// This accounts for target-specific dependencies, but currently ignores any
-/// optional dependencies enabled by features.
+/// See [which dependencies count](crate#which-dependencies-count) for how optional,
+/// target-specific, dev, and build dependencies are handled.
 ///
 /// # Example
 /// ```
@@ -92,6 +110,13 @@ pub fn cmd(args: TokenStream, input: TokenStream) -> TokenStream {
 /// ```
 ///
 /// ```
+/// #[realia::dep("syn")]
+/// fn example() {
+///     println!("This crate depends on Syn");
+/// }
+/// ```
+///
+/// ```
 /// #[realia::dep("realia", "syn", "1.0.34")]
 /// fn example() {
 ///     println!("Realia uses Syn 1.0.34 exactly");
@@ -104,14 +129,23 @@ pub fn dep(args: TokenStream, input: TokenStream) -> TokenStream {
 
 /// Checks whether a crate has a certain dependency at or above a certain version.
 ///
+/// Versions are compared according to semver precedence, where a pre-release
+/// comes before the corresponding release: `1.0.0-beta.2` is since `0.9.0`,
+/// but not since `1.0.0`. If you don't want pre-releases of the dependency
+/// to count at all, add the option `prerelease = false`.
+///
 /// The first argument is an "anchor crate", which should generally be your crate.
 /// Since this functionality is implemented by checking the output of
 /// `cargo metadata --manifest-path $CARGO_MANIFEST_DIR/Cargo.toml`,
 /// the anchor is used to ensure consistent results when `$CARGO_MANIFEST_DIR`
 /// changes (e.g., when building your crate directly vs when building a downstream crate).
+/// If you omit the anchor, then it defaults to the crate being compiled.
 ///
-/// This accounts for target-specific dependencies, but currently ignores any
-/// optional dependencies enabled by features.
+/// See [which dependencies count](crate#which-dependencies-count) for how optional,
+/// target-specific, dev, and build dependencies are handled.
+///
+/// To check the anchor's whole dependency closure instead of only its direct
+/// dependencies, add the option `transitive = true` (see [`macro@dep_transitive`]).
 ///
 /// # Example
 /// ```
@@ -120,6 +154,20 @@ pub fn dep(args: TokenStream, input: TokenStream) -> TokenStream {
 ///     println!("Realia uses Syn 1.0.34 or newer");
 /// }
 /// ```
+///
+/// ```
+/// #[realia::dep_since("syn", "1.0")]
+/// fn example() {
+///     println!("This crate uses Syn 1.0 or newer");
+/// }
+/// ```
+///
+/// ```
+/// #[realia::dep_since("realia", "syn", "1.0", prerelease = false)]
+/// fn example() {
+///     println!("Realia uses a stable release of Syn 1.0 or newer");
+/// }
+/// ```
 #[proc_macro_attribute]
 pub fn dep_since(args: TokenStream, input: TokenStream) -> TokenStream {
     cfg("dep_since", args, input)
@@ -127,14 +175,23 @@ pub fn dep_since(args: TokenStream, input: TokenStream) -> TokenStream {
 
 /// Checks whether a crate has a certain dependency below a certain version.
 ///
+/// Versions are compared according to semver precedence, where a pre-release
+/// comes before the corresponding release: `1.0.0-beta.2` is before `1.0.0`.
+/// If you don't want pre-releases of the dependency to count at all,
+/// add the option `prerelease = false`.
+///
 /// The first argument is an "anchor crate", which should generally be your crate.
 /// Since this functionality is implemented by checking the output of
 /// `cargo metadata --manifest-path $CARGO_MANIFEST_DIR/Cargo.toml`,
 /// the anchor is used to ensure consistent results when `$CARGO_MANIFEST_DIR`
 /// changes (e.g., when building your crate directly vs when building a downstream crate).
+/// If you omit the anchor, then it defaults to the crate being compiled.
+///
+/// See [which dependencies count](crate#which-dependencies-count) for how optional,
+/// target-specific, dev, and build dependencies are handled.
 ///
-/// This accounts for target-specific dependencies, but currently ignores any
-/// optional dependencies enabled by features.
+/// To check the anchor's whole dependency closure instead of only its direct
+/// dependencies, add the option `transitive = true` (see [`macro@dep_transitive`]).
 ///
 /// # Example
 /// ```
@@ -148,19 +205,466 @@ pub fn dep_before(args: TokenStream, input: TokenStream) -> TokenStream {
     cfg("dep_before", args, input)
 }
 
-/// Checks whether a crate has a certain dependency installed from the registry
-/// (as opposed to being a Git dependency or a path dependency). This is useful
+/// Checks whether a crate has a certain dependency with a version that matches
+/// a requirement, using the same syntax as in `Cargo.toml`. This lets you check
+/// ranges like `>=1.2, <1.5` as well as caret and tilde requirements.
+///
+/// Like in Cargo, a pre-release of the dependency only matches if the requirement
+/// mentions a pre-release of the same version (e.g., `>=1.0.0-beta`).
+///
+/// The first argument is an "anchor crate", which should generally be your crate.
+/// Since this functionality is implemented by checking the output of
+/// `cargo metadata --manifest-path $CARGO_MANIFEST_DIR/Cargo.toml`,
+/// the anchor is used to ensure consistent results when `$CARGO_MANIFEST_DIR`
+/// changes (e.g., when building your crate directly vs when building a downstream crate).
+/// If you omit the anchor, then it defaults to the crate being compiled.
+///
+/// See [which dependencies count](crate#which-dependencies-count) for how optional,
+/// target-specific, dev, and build dependencies are handled.
+///
+/// To check the anchor's whole dependency closure instead of only its direct
+/// dependencies, add the option `transitive = true` (see [`macro@dep_transitive`]).
+///
+/// # Example
+/// ```
+/// #[realia::dep_matches("realia", "syn", "^1.0.30, <2")]
+/// fn example() {
+///     println!("Realia uses a Syn 1.x release from 1.0.30 onward");
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn dep_matches(args: TokenStream, input: TokenStream) -> TokenStream {
+    cfg("dep_matches", args, input)
+}
+
+/// Checks whether a crate has a certain dependency anywhere in its dependency graph,
+/// not just among its direct dependencies, and optionally if that dependency is
+/// a certain version. This is like [`macro@dep`] with the option `transitive = true`.
+///
+/// This starts from the anchor's dependencies that are linked into the current
+/// compilation (as described for [`macro@dep`]) and then follows normal dependencies,
+/// so it doesn't include the build dependencies or dev-dependencies of other crates.
+///
+/// If several versions of the dependency are in the graph, then by default
+/// it's enough for any of them to match. The version checks accept a `copies`
+/// option to change that: `copies = "all"` requires every version to match,
+/// and `copies = "one"` requires exactly one of them to match.
+/// See also [`macro@dep_duplicated`].
+///
+/// The first argument is an "anchor crate", which should generally be your crate.
+/// Since this functionality is implemented by checking the output of
+/// `cargo metadata --manifest-path $CARGO_MANIFEST_DIR/Cargo.toml`,
+/// the anchor is used to ensure consistent results when `$CARGO_MANIFEST_DIR`
+/// changes (e.g., when building your crate directly vs when building a downstream crate).
+/// If you omit the anchor, then it defaults to the crate being compiled.
+///
+/// # Example
+/// ```
+/// #[realia::dep_transitive("realia", "unicode-ident")]
+/// fn example() {
+///     println!("Something that Realia depends on uses unicode-ident");
+/// }
+/// ```
+///
+/// ```
+/// #[realia::dep_since("realia", "unicode-ident", "1.0", transitive = true)]
+/// fn example() {
+///     println!("Realia's dependency graph has unicode-ident 1.0 or newer");
+/// }
+/// ```
+///
+/// ```
+/// #[realia::dep_since("realia", "semver", "0.9", transitive = true, copies = "all")]
+/// fn example() {
+///     println!("Every copy of semver in Realia's dependency graph is 0.9 or newer");
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn dep_transitive(args: TokenStream, input: TokenStream) -> TokenStream {
+    cfg("dep_transitive", args, input)
+}
+
+/// Checks whether more than one version of a certain crate is
+/// anywhere in a crate's dependency graph, as described for [`macro@dep_transitive`].
+///
+/// The first argument is an "anchor crate", which should generally be your crate.
+/// Since this functionality is implemented by checking the output of
+/// `cargo metadata --manifest-path $CARGO_MANIFEST_DIR/Cargo.toml`,
+/// the anchor is used to ensure consistent results when `$CARGO_MANIFEST_DIR`
+/// changes (e.g., when building your crate directly vs when building a downstream crate).
+/// If you omit the anchor, then it defaults to the crate being compiled.
+///
+/// # Example
+/// ```
+/// #[realia::dep_duplicated("realia", "semver")]
+/// fn example() {
+///     println!("Realia's dependency graph has several versions of semver");
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn dep_duplicated(args: TokenStream, input: TokenStream) -> TokenStream {
+    cfg("dep_duplicated", args, input)
+}
+
+/// Checks whether a crate has a certain dependency with a certain feature enabled.
+/// This is based on the features that Cargo resolved for the dependency,
+/// so it includes features enabled by other crates in the dependency graph
+/// as well as default features.
+///
+/// The first argument is an "anchor crate", which should generally be your crate.
+/// Since this functionality is implemented by checking the output of
+/// `cargo metadata --manifest-path $CARGO_MANIFEST_DIR/Cargo.toml`,
+/// the anchor is used to ensure consistent results when `$CARGO_MANIFEST_DIR`
+/// changes (e.g., when building your crate directly vs when building a downstream crate).
+/// If you omit the anchor, then it defaults to the crate being compiled.
+///
+/// See [which dependencies count](crate#which-dependencies-count) for how optional,
+/// target-specific, dev, and build dependencies are handled.
+///
+/// To check the anchor's whole dependency closure instead of only its direct
+/// dependencies, add the option `transitive = true` (see [`macro@dep_transitive`]).
+///
+/// # Example
+/// ```
+/// #[realia::dep_feature("realia", "syn", "full")]
+/// fn example() {
+///     println!("Realia uses Syn with the `full` feature");
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn dep_feature(args: TokenStream, input: TokenStream) -> TokenStream {
+    cfg("dep_feature", args, input)
+}
+
+/// Checks where a crate's dependency comes from. This is based on the source
+/// that Cargo actually resolved, so it accounts for `[patch]` sections.
+/// The possible sources are:
+///
+/// * `"path"`: A local path, including other members of your workspace.
+/// * `"git"`: A Git repository.
+/// * `"registry"`: Any registry, including alternate and sparse registries.
+/// * `"registry:<name>"`: A specific registry, by the name that you configured
+///   for it in `.cargo/config.toml` or with `CARGO_REGISTRIES_<NAME>_INDEX`.
+///   Use `"registry:crates-io"` for crates.io.
+/// * `"vendored"`: A registry or Git dependency that Cargo loads from a local
+///   directory instead, as with `cargo vendor` and source replacement.
+///   Such a dependency also still counts as `"registry"` or `"git"`.
+///
+/// The first argument is an "anchor crate", which should generally be your crate.
+/// Since this functionality is implemented by checking the output of
+/// `cargo metadata --manifest-path $CARGO_MANIFEST_DIR/Cargo.toml`,
+/// the anchor is used to ensure consistent results when `$CARGO_MANIFEST_DIR`
+/// changes (e.g., when building your crate directly vs when building a downstream crate).
+/// If you omit the anchor, then it defaults to the crate being compiled.
+///
+/// See [which dependencies count](crate#which-dependencies-count) for how optional,
+/// target-specific, dev, and build dependencies are handled.
+///
+/// To check the anchor's whole dependency closure instead of only its direct
+/// dependencies, add the option `transitive = true` (see [`macro@dep_transitive`]).
+///
+/// # Example
+/// ```
+/// #[realia::dep_source("realia", "syn", "registry:crates-io")]
+/// fn example() {
+///     println!("Realia uses Syn from crates.io");
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn dep_source(args: TokenStream, input: TokenStream) -> TokenStream {
+    cfg("dep_source", args, input)
+}
+
+/// Checks whether a crate has a certain dependency from a Git repository,
+/// and optionally which revision, branch, or tag it uses:
+///
+/// * `rev = "..."` matches the commit that Cargo resolved the dependency to.
+///   You can abbreviate the commit hash to a prefix of at least 4 hexadecimal
+///   characters, like `rev = "4a5b3c2"`.
+///   This also matches if the manifest specified exactly the same `rev`,
+///   even if it's some other kind of Git ref.
+/// * `branch = "..."` and `tag = "..."` match the `branch` or `tag`
+///   specified in the manifest. A dependency on the default branch
+///   doesn't have a branch name, so it won't match any `branch`.
+///
+/// If you specify several of these, then all of them need to match.
+///
+/// The first argument is an "anchor crate", which should generally be your crate.
+/// Since this functionality is implemented by checking the output of
+/// `cargo metadata --manifest-path $CARGO_MANIFEST_DIR/Cargo.toml`,
+/// the anchor is used to ensure consistent results when `$CARGO_MANIFEST_DIR`
+/// changes (e.g., when building your crate directly vs when building a downstream crate).
+/// If you omit the anchor, then it defaults to the crate being compiled.
+///
+/// See [which dependencies count](crate#which-dependencies-count) for how optional,
+/// target-specific, dev, and build dependencies are handled.
+///
+/// To check the anchor's whole dependency closure instead of only its direct
+/// dependencies, add the option `transitive = true` (see [`macro@dep_transitive`]).
+///
+/// # Example
+/// ```
+/// #[realia::not(dep_git("realia", "syn", branch = "master"))]
+/// fn example() {
+///     println!("Realia doesn't use Syn from its master branch");
+/// }
+/// ```
+///
+/// ```
+/// #[realia::not(dep_git("realia", "syn", rev = "4a5b3c2"))]
+/// fn example() {
+///     println!("Realia doesn't use commit 4a5b3c2 of Syn");
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn dep_git(args: TokenStream, input: TokenStream) -> TokenStream {
+    cfg("dep_git", args, input)
+}
+
+/// Checks whether a crate's dependency is overridden by a `[patch]` or `[replace]`
+/// section, meaning that Cargo resolved it from a different source than
+/// the one declared in the manifest.
+///
+/// This can keep a temporary patch from shipping by failing release builds:
+///
+/// ```
+/// #[cfg(not(debug_assertions))]
+/// #[realia::dep_patched("realia", "syn")]
+/// compile_error!("Syn is still patched");
+/// ```
+///
+/// The first argument is an "anchor crate", which should generally be your crate.
+/// Since this functionality is implemented by checking the output of
+/// `cargo metadata --manifest-path $CARGO_MANIFEST_DIR/Cargo.toml`,
+/// the anchor is used to ensure consistent results when `$CARGO_MANIFEST_DIR`
+/// changes (e.g., when building your crate directly vs when building a downstream crate).
+/// If you omit the anchor, then it defaults to the crate being compiled.
+///
+/// See [which dependencies count](crate#which-dependencies-count) for how optional,
+/// target-specific, dev, and build dependencies are handled.
+///
+/// To check the anchor's whole dependency closure instead of only its direct
+/// dependencies, add the option `transitive = true` (see [`macro@dep_transitive`]).
+/// In that case, each dependency is compared with how the crate that depends on it
+/// declares it.
+///
+/// # Example
+/// ```
+/// #[realia::not(dep_patched("realia", "syn"))]
+/// fn example() {
+///     println!("Realia uses Syn as declared");
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn dep_patched(args: TokenStream, input: TokenStream) -> TokenStream {
+    cfg("dep_patched", args, input)
+}
+
+/// Checks whether anything in a crate's dependency graph links a certain native
+/// library, as declared with `links = "..."` in its manifest. This is typically
+/// a `-sys` crate, so you can tell which library is in use, regardless of which
+/// crate provides it.
+///
+/// The dependency graph is the same as for [`macro@dep_transitive`],
+/// including the anchor itself.
+///
+/// The first argument is an "anchor crate", which should generally be your crate.
+/// Since this functionality is implemented by checking the output of
+/// `cargo metadata --manifest-path $CARGO_MANIFEST_DIR/Cargo.toml`,
+/// the anchor is used to ensure consistent results when `$CARGO_MANIFEST_DIR`
+/// changes (e.g., when building your crate directly vs when building a downstream crate).
+/// If you omit the anchor, then it defaults to the crate being compiled.
+///
+/// # Example
+/// ```
+/// #[realia::not(dep_links("realia", "openssl"))]
+/// fn example() {
+///     println!("Nothing in Realia's dependency graph links OpenSSL");
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn dep_links(args: TokenStream, input: TokenStream) -> TokenStream {
+    cfg("dep_links", args, input)
+}
+
+/// Checks a value in the `[package.metadata]` table of a crate's dependency,
+/// or of the anchor itself if you pass its name as the dependency.
+/// The value is identified by a dotted path, so `"ourcorp.api_level"` refers to
+/// `api_level` in `[package.metadata.ourcorp]`. If you don't specify the expected
+/// value, then this checks that the path exists.
+///
+/// The expected value can be a string, number, or boolean. A string also matches
+/// a number or boolean with the same text, so `"3"` matches `api_level = 3`.
+/// Like with [`macro@dep`], you can only omit the anchor if you don't specify
+/// the expected value as a string.
+///
+/// The first argument is an "anchor crate", which should generally be your crate.
+/// Since this functionality is implemented by checking the output of
+/// `cargo metadata --manifest-path $CARGO_MANIFEST_DIR/Cargo.toml`,
+/// the anchor is used to ensure consistent results when `$CARGO_MANIFEST_DIR`
+/// changes (e.g., when building your crate directly vs when building a downstream crate).
+/// If you omit the anchor, then it defaults to the crate being compiled.
+///
+/// See [which dependencies count](crate#which-dependencies-count) for how optional,
+/// target-specific, dev, and build dependencies are handled.
+///
+/// To check the anchor's whole dependency closure instead of only its direct
+/// dependencies, add the option `transitive = true` (see [`macro@dep_transitive`]).
+///
+/// # Example
+/// ```ignore
+/// #[realia::dep_metadata("your-crate", "foo", "ourcorp.api_level", 3)]
+/// fn example() {
+///     println!("foo has api_level = 3 in [package.metadata.ourcorp]");
+/// }
+/// ```
+///
+/// ```ignore
+/// #[realia::dep_metadata("your-crate", "foo", "ourcorp.api_level")]
+/// fn example() {
+///     println!("foo has an api_level in [package.metadata.ourcorp]");
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn dep_metadata(args: TokenStream, input: TokenStream) -> TokenStream {
+    cfg("dep_metadata", args, input)
+}
+
+/// Checks whether a crate's dependency declares a `rust-version` (its minimum supported
+/// Rust version) that is at least a certain version. Pass the anchor's own name as the
+/// dependency to check the anchor itself. A crate that doesn't declare a `rust-version`
+/// never matches, and the comparison ignores any pre-release part of the version.
+///
+/// The first argument is an "anchor crate", which should generally be your crate.
+/// Since this functionality is implemented by checking the output of
+/// `cargo metadata --manifest-path $CARGO_MANIFEST_DIR/Cargo.toml`,
+/// the anchor is used to ensure consistent results when `$CARGO_MANIFEST_DIR`
+/// changes (e.g., when building your crate directly vs when building a downstream crate).
+/// If you omit the anchor, then it defaults to the crate being compiled.
+///
+/// See [which dependencies count](crate#which-dependencies-count) for how optional,
+/// target-specific, dev, and build dependencies are handled.
+///
+/// To check the anchor's whole dependency closure instead of only its direct
+/// dependencies, add the option `transitive = true` (see [`macro@dep_transitive`]).
+///
+/// # Example
+/// ```
+/// #[realia::dep_msrv_since("realia", "syn", "1.31")]
+/// fn example() {
+///     println!("Realia's syn dependency requires at least Rust 1.31");
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn dep_msrv_since(args: TokenStream, input: TokenStream) -> TokenStream {
+    cfg("dep_msrv_since", args, input)
+}
+
+/// Checks whether a crate's dependency declares a `rust-version` (its minimum supported
+/// Rust version) that is older than a certain version. Pass the anchor's own name as the
+/// dependency to check the anchor itself. A crate that doesn't declare a `rust-version`
+/// never matches, and the comparison ignores any pre-release part of the version.
+///
+/// The first argument is an "anchor crate", which should generally be your crate.
+/// Since this functionality is implemented by checking the output of
+/// `cargo metadata --manifest-path $CARGO_MANIFEST_DIR/Cargo.toml`,
+/// the anchor is used to ensure consistent results when `$CARGO_MANIFEST_DIR`
+/// changes (e.g., when building your crate directly vs when building a downstream crate).
+/// If you omit the anchor, then it defaults to the crate being compiled.
+///
+/// See [which dependencies count](crate#which-dependencies-count) for how optional,
+/// target-specific, dev, and build dependencies are handled.
+///
+/// To check the anchor's whole dependency closure instead of only its direct
+/// dependencies, add the option `transitive = true` (see [`macro@dep_transitive`]).
+///
+/// # Example
+/// ```
+/// #[realia::dep_msrv_before("realia", "syn", "1.56")]
+/// fn example() {
+///     println!("Realia's syn dependency supports Rust versions before 1.56");
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn dep_msrv_before(args: TokenStream, input: TokenStream) -> TokenStream {
+    cfg("dep_msrv_before", args, input)
+}
+
+/// Checks whether the active Rust compiler is new enough for a crate's dependency,
+/// based on the `rust-version` that the dependency declares. Pass the anchor's own name
+/// as the dependency to check the anchor itself. A crate that doesn't declare
+/// a `rust-version` is assumed to be supported.
+///
+/// The compiler version comes from `rustc -Vv` (or `$RUSTC -Vv`). Like Cargo,
+/// this treats nightly and beta compilers the same as the corresponding release,
+/// so `1.80.0-nightly` satisfies `rust-version = "1.80"`.
+///
+/// The first argument is an "anchor crate", which should generally be your crate.
+/// Since this functionality is implemented by checking the output of
+/// `cargo metadata --manifest-path $CARGO_MANIFEST_DIR/Cargo.toml`,
+/// the anchor is used to ensure consistent results when `$CARGO_MANIFEST_DIR`
+/// changes (e.g., when building your crate directly vs when building a downstream crate).
+/// If you omit the anchor, then it defaults to the crate being compiled.
+///
+/// See [which dependencies count](crate#which-dependencies-count) for how optional,
+/// target-specific, dev, and build dependencies are handled.
+///
+/// To check the anchor's whole dependency closure instead of only its direct
+/// dependencies, add the option `transitive = true` (see [`macro@dep_transitive`]).
+///
+/// # Example
+/// ```
+/// #[realia::dep_msrv_supported("realia", "syn")]
+/// fn example() {
+///     println!("The current compiler is new enough for Realia's syn dependency");
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn dep_msrv_supported(args: TokenStream, input: TokenStream) -> TokenStream {
+    cfg("dep_msrv_supported", args, input)
+}
+
+/// Checks whether a crate declares a certain dependency as a certain kind:
+/// `"normal"`, `"dev"`, or `"build"`. Unlike the other dependency checks,
+/// this doesn't care whether the dependency is linked into the current compilation,
+/// so it also counts optional dependencies that aren't enabled
+/// and dependencies for other targets.
+///
+/// The first argument is an "anchor crate", which should generally be your crate.
+/// Since this functionality is implemented by checking the output of
+/// `cargo metadata --manifest-path $CARGO_MANIFEST_DIR/Cargo.toml`,
+/// the anchor is used to ensure consistent results when `$CARGO_MANIFEST_DIR`
+/// changes (e.g., when building your crate directly vs when building a downstream crate).
+/// If you omit the anchor, then it defaults to the crate being compiled.
+///
+/// # Example
+/// ```
+/// #[realia::dep_kind("realia", "version-compare", "dev")]
+/// fn example() {
+///     println!("Realia uses version-compare for testing");
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn dep_kind(args: TokenStream, input: TokenStream) -> TokenStream {
+    cfg("dep_kind", args, input)
+}
+
+/// Checks whether a crate has a certain dependency installed from a registry
+/// (as opposed to being a Git dependency or a path dependency),
+/// based on how the dependency is declared in the anchor's manifest. This is useful
 /// if you have non-registry dependencies with a
 /// [registry fallback for publishing](https://doc.rust-lang.org/cargo/reference/specifying-dependencies.html#multiple-locations).
+/// To check where the dependency was actually resolved from, use [`macro@dep_source`].
 ///
 /// The first argument is an "anchor crate", which should generally be your crate.
 /// Since this functionality is implemented by checking the output of
 /// `cargo metadata --manifest-path $CARGO_MANIFEST_DIR/Cargo.toml`,
 /// the anchor is used to ensure consistent results when `$CARGO_MANIFEST_DIR`
 /// changes (e.g., when building your crate directly vs when building a downstream crate).
+/// If you omit the anchor, then it defaults to the crate being compiled.
 ///
-/// This accounts for target-specific dependencies, but currently ignores any
-/// optional dependencies enabled by features.
+/// See [which dependencies count](crate#which-dependencies-count) for how optional,
+/// target-specific, dev, and build dependencies are handled.
 ///
 /// # Example
 /// ```
@@ -174,6 +678,32 @@ pub fn dep_from_registry(args: TokenStream, input: TokenStream) -> TokenStream {
     cfg("dep_from_registry", args, input)
 }
 
+/// Checks whether a crate has a dependency under a certain local name,
+/// as when renaming it with `foo2 = { package = "foo", version = "2" }`
+/// in `Cargo.toml`.
+///
+/// Note that the other dependency checks match either the package name or
+/// the local name, so this is only needed if you care about the rename itself.
+///
+/// The first argument is an "anchor crate", which should generally be your crate.
+/// Since this functionality is implemented by checking the output of
+/// `cargo metadata --manifest-path $CARGO_MANIFEST_DIR/Cargo.toml`,
+/// the anchor is used to ensure consistent results when `$CARGO_MANIFEST_DIR`
+/// changes (e.g., when building your crate directly vs when building a downstream crate).
+/// If you omit the anchor, then it defaults to the crate being compiled.
+///
+/// # Example
+/// ```
+/// #[realia::not(dep_renamed("realia", "syn"))]
+/// fn example() {
+///     println!("Realia does not rename Syn");
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn dep_renamed(args: TokenStream, input: TokenStream) -> TokenStream {
+    cfg("dep_renamed", args, input)
+}
+
 /// Inverts another condition.
 ///
 /// # Example
@@ -217,9 +747,17 @@ pub fn all(args: TokenStream, input: TokenStream) -> TokenStream {
 }
 
 fn cfg(top: &str, args: TokenStream, input: TokenStream) -> TokenStream {
-    match try_cfg(top, args, input) {
-        Ok(tokens) => tokens,
-        Err(err) => TokenStream::from(err.to_compile_error()),
+    let original = TokenStream2::from(input.clone());
+    finish(try_cfg(top, args, input), &original)
+}
+
+fn finish(result: Result<TokenStream>, original: &TokenStream2) -> TokenStream {
+    match result {
+        Ok(tokens) => TokenStream::from(diagnostic::attach(TokenStream2::from(tokens), original)),
+        Err(err) => {
+            diagnostic::clear();
+            TokenStream::from(err.to_compile_error())
+        }
     }
 }
 
@@ -234,7 +772,7 @@ fn try_cfg(top: &str, args: TokenStream, input: TokenStream) -> Result<TokenStre
 
     let expr: Expr = syn::parse2(full_args)?;
 
-    if expr.eval() {
+    if expr.eval()? {
         Ok(input)
     } else {
         Ok(TokenStream::new())
@@ -257,16 +795,13 @@ fn try_cfg(top: &str, args: TokenStream, input: TokenStream) -> Result<TokenStre
 /// ```
 #[proc_macro_attribute]
 pub fn attr(args: TokenStream, input: TokenStream) -> TokenStream {
-    let args = parse_macro_input!(args as attr::Args);
-
-    match try_attr(args, input) {
-        Ok(tokens) => tokens,
-        Err(err) => TokenStream::from(err.to_compile_error()),
-    }
+    let original = TokenStream2::from(input.clone());
+    let result = syn::parse::<attr::Args>(args).and_then(|args| try_attr(args, input));
+    finish(result, &original)
 }
 
 fn try_attr(args: attr::Args, input: TokenStream) -> Result<TokenStream> {
-    if !args.condition.eval() {
+    if !args.condition.eval()? {
         return Ok(input);
     }
 
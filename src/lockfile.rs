@@ -0,0 +1,1017 @@
+//! Fallback for when `cargo metadata` can't be run, e.g. in sandboxed builds,
+//! non-Cargo build systems, or IDEs with an unusual environment.
+//!
+//! This assembles the same JSON that `cargo metadata` would print, but only from
+//! what `Cargo.lock` and the workspace's own `Cargo.toml` files say directly:
+//! which packages are locked and how they depend on each other, plus everything
+//! that local packages declare. The manifests of registry, Git, and vendored
+//! packages are never read, and Cargo's feature resolution isn't repeated, so
+//! whatever depends on them is listed in each package's `realia_unknown` field
+//! for the checks to report instead of guessing.
+
+use crate::metadata::Query;
+use anyhow::{anyhow, Context, Error};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    fs,
+    path::{Path, PathBuf},
+};
+use toml::Value as Toml;
+
+const CRATES_IO: &str = "registry+https://github.com/rust-lang/crates.io-index";
+
+/// The tables that declare dependencies, along with the kind that `cargo metadata` reports.
+const DEPENDENCY_TABLES: &[(&str, Option<&str>)] = &[
+    ("dependencies", None),
+    ("dev-dependencies", Some("dev")),
+    ("dev_dependencies", Some("dev")),
+    ("build-dependencies", Some("build")),
+    ("build_dependencies", Some("build")),
+];
+
+/// What's unknown about a package whose manifest isn't read.
+const UNREAD: &[&str] = &[
+    "dependencies",
+    "features",
+    "links",
+    "metadata",
+    "rust_version",
+    "manifest_path",
+];
+
+/// A `[[package]]` entry in `Cargo.lock`.
+struct Locked {
+    name: String,
+    version: String,
+    source: Option<String>,
+    dependencies: Vec<String>,
+    replace: Option<String>,
+}
+
+impl Locked {
+    fn id(&self, manifest: Option<&Manifest>) -> String {
+        match (&self.source, manifest) {
+            (Some(source), _) => format!("{} {} ({})", self.name, self.version, source),
+            (None, Some(manifest)) => format!(
+                "{} {} (path+file://{})",
+                self.name,
+                self.version,
+                manifest.dir().display()
+            ),
+            (None, None) => format!("{} {}", self.name, self.version),
+        }
+    }
+}
+
+/// A local package manifest, with the workspace's inherited values already filled in.
+struct Manifest {
+    path: PathBuf,
+    toml: Toml,
+    name: String,
+    version: String,
+    rust_version: Option<String>,
+    dependencies: Vec<Declared>,
+}
+
+impl Manifest {
+    fn dir(&self) -> &Path {
+        self.path.parent().unwrap_or(&self.path)
+    }
+
+    fn package(&self, key: &str) -> Option<&Toml> {
+        self.toml.get("package").and_then(|package| package.get(key))
+    }
+
+    fn features(&self) -> Option<&toml::value::Table> {
+        self.toml.get("features").and_then(Toml::as_table)
+    }
+}
+
+/// A dependency as declared in a manifest.
+struct Declared {
+    name: String,
+    rename: Option<String>,
+    req: String,
+    kind: Option<&'static str>,
+    optional: bool,
+    default_features: bool,
+    features: Vec<String>,
+    target: Option<String>,
+    source: Option<String>,
+    registry: Option<String>,
+    path: Option<PathBuf>,
+}
+
+impl Declared {
+    /// The name that the declaring package uses for this dependency in its features.
+    fn local_name(&self) -> &str {
+        self.rename.as_deref().unwrap_or(&self.name)
+    }
+}
+
+/// Assembles the equivalent of `cargo metadata` output from `Cargo.lock` and the local manifests.
+pub fn read_metadata(query: &Query) -> Result<String, Error> {
+    let manifest_dir = Path::new(&query.manifest_dir);
+    let root = manifest_dir
+        .ancestors()
+        .find(|dir| dir.join("Cargo.lock").is_file())
+        .ok_or_else(|| anyhow!("unable to find Cargo.lock in {} or its parents", manifest_dir.display()))?;
+    let locked = read_lock(&root.join("Cargo.lock"))?;
+    let root_toml = read_toml(&root.join("Cargo.toml"))?;
+    let workspace = root_toml.get("workspace").cloned().unwrap_or_else(empty_table);
+
+    // Read the package being compiled, the workspace members that are listed by path,
+    // and the local packages that those depend on or patch in. Members listed with
+    // a wildcard are only read if something depends on them.
+    let mut queue: VecDeque<PathBuf> = VecDeque::new();
+    queue.push_back(manifest_dir.join("Cargo.toml"));
+    queue.push_back(root.join("Cargo.toml"));
+    for member in strings(workspace.get("members")) {
+        if !member.contains(['*', '?', '[']) {
+            queue.push_back(root.join(member).join("Cargo.toml"));
+        }
+    }
+    let patches = root_toml
+        .get("patch")
+        .and_then(Toml::as_table)
+        .into_iter()
+        .flat_map(|x| x.values())
+        .filter_map(Toml::as_table);
+    let replacements = root_toml.get("replace").and_then(Toml::as_table);
+    for spec in patches.chain(replacements).flat_map(|x| x.values()) {
+        if let Some(path) = spec.get("path").and_then(Toml::as_str) {
+            queue.push_back(root.join(path).join("Cargo.toml"));
+        }
+    }
+    let mut local: Vec<Manifest> = vec![];
+    while let Some(path) = queue.pop_front() {
+        let path = fs::canonicalize(&path).unwrap_or(path);
+        if local.iter().any(|x| x.path == path) || !path.is_file() {
+            continue;
+        }
+        let manifest = match read_manifest(&path, root, &workspace)? {
+            Some(manifest) => manifest,
+            None => continue,
+        };
+        queue.extend(
+            manifest
+                .dependencies
+                .iter()
+                .filter_map(|dep| dep.path.as_ref().map(|path| path.join("Cargo.toml"))),
+        );
+        local.push(manifest);
+    }
+
+    // Only local packages have a manifest, since they're the ones without a source.
+    let manifests: Vec<Option<&Manifest>> = locked
+        .iter()
+        .map(|package| match package.source {
+            Some(_) => None,
+            None => local
+                .iter()
+                .find(|x| x.name == package.name && x.version == package.version),
+        })
+        .collect();
+    let ids: Vec<String> = (0..locked.len()).map(|i| locked[i].id(manifests[i])).collect();
+    let current = fs::canonicalize(manifest_dir).unwrap_or_else(|_| manifest_dir.to_path_buf());
+    let anchor = (0..locked.len()).find(|&i| manifests[i].iter().any(|m| m.dir() == current));
+
+    // Resolve each locked dependency to a package, following `[replace]`.
+    let find = |reference: &str| find_locked(&locked, reference);
+    let resolve = |i: usize| match locked[i].replace.as_deref().and_then(find) {
+        Some(replacement) => replacement,
+        None => i,
+    };
+
+    let mut packages = vec![];
+    let mut nodes = vec![];
+    for (i, package) in locked.iter().enumerate() {
+        if package.replace.is_some() {
+            continue;
+        }
+        let manifest = manifests[i];
+        // rustc is told which features are enabled on the crate being compiled,
+        // but finding those of any other package would mean resolving them like Cargo.
+        let features = match (Some(i) == anchor, &query.features) {
+            (true, Some(features)) => Some(features),
+            _ => None,
+        };
+        let mut unknown: Vec<&str> = match manifest {
+            Some(_) if features.is_some() => vec![],
+            Some(_) => vec!["features"],
+            None => UNREAD.to_vec(),
+        };
+
+        let mut deps = vec![];
+        for reference in &package.dependencies {
+            let child = match find(reference) {
+                Some(child) => resolve(child),
+                None => continue,
+            };
+            let declared: Vec<&Declared> = match manifest {
+                Some(manifest) => {
+                    let declared = matching_declarations(manifest, &locked[child]);
+                    let used: Vec<&Declared> = declared
+                        .iter()
+                        .copied()
+                        .filter(|dep| {
+                            dep.target
+                                .iter()
+                                .all(|target| target_spec::eval(target, &query.target) == Ok(Some(true)))
+                        })
+                        .filter(|dep| match features {
+                            Some(features) => !dep.optional || enables(manifest, features, dep.local_name()),
+                            None => true,
+                        })
+                        .collect();
+                    if !declared.is_empty() && used.is_empty() {
+                        continue;
+                    }
+                    if features.is_none() && used.iter().any(|dep| dep.optional) && !unknown.contains(&"optional") {
+                        unknown.push("optional");
+                    }
+                    used
+                }
+                None => vec![],
+            };
+            deps.push((child, declared));
+        }
+
+        packages.push(package_json(package, &ids[i], manifest, &unknown));
+        let node_deps: Vec<Value> = deps
+            .iter()
+            .map(|(child, declared)| {
+                let name = match declared.first() {
+                    Some(dep) => dep.local_name(),
+                    None => &locked[*child].name,
+                };
+                let kinds: Vec<Value> = declared
+                    .iter()
+                    .map(|dep| json!({ "kind": dep.kind, "target": dep.target }))
+                    .collect();
+                json!({ "name": name.replace('-', "_"), "pkg": ids[*child], "dep_kinds": kinds })
+            })
+            .collect();
+        let dependencies: Vec<&String> = deps.iter().map(|(child, _)| &ids[*child]).collect();
+        nodes.push(json!({
+            "id": ids[i],
+            "dependencies": dependencies,
+            "deps": node_deps,
+            "features": features.cloned().unwrap_or_default(),
+        }));
+    }
+
+    // Path dependencies inside the workspace's folder are members too.
+    let workspace_members: Vec<&String> = (0..locked.len())
+        .filter(|&i| locked[i].replace.is_none() && manifests[i].iter().any(|m| m.path.starts_with(root)))
+        .map(|i| &ids[i])
+        .collect();
+    let metadata = json!({
+        "packages": packages,
+        "workspace_members": workspace_members,
+        "resolve": { "nodes": nodes, "root": anchor.map(|i| &ids[i]) },
+        "workspace_root": root,
+        "target_directory": root.join("target"),
+        "version": 1,
+    });
+    Ok(metadata.to_string())
+}
+
+fn read_toml(path: &Path) -> Result<Toml, Error> {
+    let content = fs::read_to_string(path).with_context(|| format!("error reading {}", path.display()))?;
+    content
+        .parse()
+        .with_context(|| format!("error parsing {}", path.display()))
+}
+
+fn empty_table() -> Toml {
+    Toml::Table(Default::default())
+}
+
+fn strings(value: Option<&Toml>) -> Vec<String> {
+    value
+        .and_then(Toml::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|x| x.as_str().map(str::to_string))
+        .collect()
+}
+
+fn read_lock(path: &Path) -> Result<Vec<Locked>, Error> {
+    let lock = read_toml(path)?;
+    Ok(lock
+        .get("package")
+        .and_then(Toml::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|package| {
+            Some(Locked {
+                name: package.get("name")?.as_str()?.to_string(),
+                version: package.get("version")?.as_str()?.to_string(),
+                source: package.get("source").and_then(Toml::as_str).map(str::to_string),
+                dependencies: strings(package.get("dependencies")),
+                replace: package.get("replace").and_then(Toml::as_str).map(str::to_string),
+            })
+        })
+        .collect())
+}
+
+/// Finds a package from a reference in `Cargo.lock`, which looks like `name`,
+/// `name version`, or `name version (source)`, depending on what's needed to tell
+/// the packages apart. Local packages never have their source written, so they're
+/// preferred when it's omitted (e.g., for the target of a `[replace]`).
+fn find_locked(locked: &[Locked], reference: &str) -> Option<usize> {
+    let mut parts = reference.splitn(3, ' ');
+    let name = parts.next()?;
+    let version = parts.next();
+    let source = parts.next().map(|x| x.trim_start_matches('(').trim_end_matches(')'));
+    let matches = |x: &Locked| {
+        x.name == name
+            && version.iter().all(|version| x.version == *version)
+            && source.iter().all(|source| x.source.as_deref() == Some(*source))
+    };
+    locked
+        .iter()
+        .position(|x| source.is_none() && x.source.is_none() && matches(x))
+        .or_else(|| locked.iter().position(matches))
+}
+
+/// Reads a local package's manifest, or returns `None` if it's only a virtual workspace manifest.
+fn read_manifest(path: &Path, root: &Path, workspace: &Toml) -> Result<Option<Manifest>, Error> {
+    let toml = read_toml(path)?;
+    let package = match toml.get("package") {
+        Some(package) => package,
+        None => return Ok(None),
+    };
+    let dir = path.parent().unwrap_or(path);
+    let inherited = |key: &str| {
+        let value = package.get(key)?;
+        if value.get("workspace").and_then(Toml::as_bool) == Some(true) {
+            workspace.get("package")?.get(key)?.as_str().map(str::to_string)
+        } else {
+            value.as_str().map(str::to_string)
+        }
+    };
+
+    let mut dependencies = vec![];
+    let mut tables: Vec<(Option<String>, &Toml)> = vec![(None, &toml)];
+    for (target, table) in toml.get("target").and_then(Toml::as_table).into_iter().flatten() {
+        tables.push((Some(target.clone()), table));
+    }
+    for (target, table) in tables {
+        for (key, kind) in DEPENDENCY_TABLES {
+            for (local, spec) in table.get(*key).and_then(Toml::as_table).into_iter().flatten() {
+                dependencies.push(declared(local, spec, *kind, target.clone(), dir, root, workspace));
+            }
+        }
+    }
+
+    Ok(Some(Manifest {
+        path: fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()),
+        name: package
+            .get("name")
+            .and_then(Toml::as_str)
+            .ok_or_else(|| anyhow!("{} doesn't have a package name", path.display()))?
+            .to_string(),
+        version: inherited("version").unwrap_or_else(|| "0.0.0".to_string()),
+        rust_version: inherited("rust-version"),
+        dependencies,
+        toml,
+    }))
+}
+
+/// Interprets a dependency declaration, which may inherit from `[workspace.dependencies]`.
+fn declared(
+    local: &str,
+    spec: &Toml,
+    kind: Option<&'static str>,
+    target: Option<String>,
+    dir: &Path,
+    root: &Path,
+    workspace: &Toml,
+) -> Declared {
+    let (base, base_dir) = if spec.get("workspace").and_then(Toml::as_bool) == Some(true) {
+        let base = workspace
+            .get("dependencies")
+            .and_then(|deps| deps.get(local))
+            .cloned()
+            .unwrap_or_else(empty_table);
+        (base, root)
+    } else {
+        (spec.clone(), dir)
+    };
+    let get = |key: &str| spec.get(key).or_else(|| base.get(key));
+    let get_str = |key: &str| get(key).and_then(Toml::as_str).map(str::to_string);
+
+    let name = get_str("package").unwrap_or_else(|| local.to_string());
+    let registry = get_str("registry");
+    let source = if let Some(git) = get_str("git") {
+        let reference = ["branch", "tag", "rev"]
+            .iter()
+            .find_map(|key| get_str(key).map(|value| format!("?{}={}", key, value)));
+        Some(format!("git+{}{}", git, reference.unwrap_or_default()))
+    } else if get("path").is_some() {
+        None
+    } else if let Some(registry) = &registry {
+        match crate::source::registry_indices(registry) {
+            Ok(Some(indices)) => indices.first().map(|index| format!("registry+{}", index)),
+            _ => None,
+        }
+    } else {
+        Some(CRATES_IO.to_string())
+    };
+
+    let mut features = strings(base.get("features"));
+    features.extend(strings(spec.get("features")));
+    Declared {
+        rename: if name == local { None } else { Some(local.to_string()) },
+        name,
+        req: base
+            .as_str()
+            .map(str::to_string)
+            .or_else(|| get_str("version"))
+            .unwrap_or_else(|| "*".to_string()),
+        kind,
+        optional: spec.get("optional").and_then(Toml::as_bool).unwrap_or(false),
+        default_features: get("default-features")
+            .or_else(|| get("default_features"))
+            .and_then(Toml::as_bool)
+            .unwrap_or(true),
+        features,
+        target,
+        source,
+        registry,
+        path: get_str("path").map(|path| base_dir.join(path)),
+    }
+}
+
+/// Finds the declarations that refer to a locked package. If a crate is declared
+/// more than once (e.g., renamed for different versions), then this goes by
+/// the version requirements.
+fn matching_declarations<'a>(manifest: &'a Manifest, package: &Locked) -> Vec<&'a Declared> {
+    let named: Vec<&Declared> = manifest
+        .dependencies
+        .iter()
+        .filter(|dep| dep.name == package.name)
+        .collect();
+    let version = semver::Version::parse(&package.version).ok();
+    let matching: Vec<&Declared> = named
+        .iter()
+        .copied()
+        .filter(|dep| match (semver::VersionReq::parse(&dep.req), &version) {
+            (Ok(req), Some(version)) => req.matches(version),
+            _ => true,
+        })
+        .collect();
+    if matching.is_empty() {
+        named
+    } else {
+        matching
+    }
+}
+
+/// Checks if an optional dependency is enabled by a package's features. Cargo passes
+/// every enabled feature to rustc, so this only needs to look one level deep.
+fn enables(manifest: &Manifest, features: &[String], dep: &str) -> bool {
+    let table = manifest.features();
+    features.iter().any(|feature| {
+        // An optional dependency has a feature of the same name unless it's referred to with `dep:`.
+        feature == dep
+            || strings(table.and_then(|table| table.get(feature)))
+                .iter()
+                .any(|x| x.strip_prefix("dep:") == Some(dep) || x.split_once('/').map(|(name, _)| name) == Some(dep))
+    })
+}
+
+fn package_json(package: &Locked, id: &str, manifest: Option<&Manifest>, unknown: &[&str]) -> Value {
+    let manifest = match manifest {
+        Some(manifest) => manifest,
+        None => {
+            return json!({
+                "name": package.name,
+                "version": package.version,
+                "id": id,
+                "source": package.source,
+                "dependencies": [],
+                "targets": [],
+                "features": {},
+                "manifest_path": "",
+                "realia_unknown": unknown,
+            })
+        }
+    };
+    let dependencies: Vec<Value> = manifest
+        .dependencies
+        .iter()
+        .map(|dep| {
+            json!({
+                "name": dep.name,
+                "source": dep.source,
+                "req": dep.req,
+                "kind": dep.kind,
+                "optional": dep.optional,
+                "uses_default_features": dep.default_features,
+                "features": dep.features,
+                "target": dep.target,
+                "rename": dep.rename,
+                "registry": dep.registry,
+            })
+        })
+        .collect();
+    let features: HashMap<&String, Vec<String>> = manifest
+        .features()
+        .into_iter()
+        .flatten()
+        .map(|(name, enables)| (name, strings(Some(enables))))
+        .collect();
+    let metadata = manifest
+        .package("metadata")
+        .and_then(|x| serde_json::to_value(x).ok())
+        .unwrap_or(Value::Null);
+    json!({
+        "name": package.name,
+        "version": package.version,
+        "id": id,
+        "source": package.source,
+        "dependencies": dependencies,
+        "targets": targets(manifest),
+        "features": features,
+        "manifest_path": manifest.path,
+        "metadata": metadata,
+        "links": manifest.package("links").and_then(Toml::as_str),
+        "rust_version": manifest.rust_version,
+        "realia_unknown": unknown,
+    })
+}
+
+/// Lists a local package's targets, both declared and automatically discovered.
+/// The checks only care about their names and kinds.
+fn targets(manifest: &Manifest) -> Vec<Value> {
+    let dir = manifest.dir();
+    let mut targets = vec![];
+    let mut add = |name: String, kind: &str, path: PathBuf| {
+        targets.push(json!({ "name": name, "kind": [kind], "src_path": path }));
+    };
+
+    let lib = manifest.toml.get("lib");
+    if lib.is_some() || dir.join("src/lib.rs").is_file() {
+        let name = lib
+            .and_then(|x| x.get("name"))
+            .and_then(Toml::as_str)
+            .map(str::to_string)
+            .unwrap_or_else(|| manifest.name.replace('-', "_"));
+        let kind = match lib.and_then(|x| x.get("proc-macro")).and_then(Toml::as_bool) {
+            Some(true) => "proc-macro",
+            _ => "lib",
+        };
+        add(name, kind, dir.join("src/lib.rs"));
+    }
+    if dir.join("src/main.rs").is_file() {
+        add(manifest.name.clone(), "bin", dir.join("src/main.rs"));
+    }
+    if manifest.package("build").is_some() || dir.join("build.rs").is_file() {
+        add("build-script-build".to_string(), "custom-build", dir.join("build.rs"));
+    }
+
+    for (table, folder, kind) in &[
+        ("bin", "src/bin", "bin"),
+        ("example", "examples", "example"),
+        ("test", "tests", "test"),
+        ("bench", "benches", "bench"),
+    ] {
+        let mut names = BTreeSet::new();
+        for target in manifest.toml.get(*table).and_then(Toml::as_array).into_iter().flatten() {
+            if let Some(name) = target.get("name").and_then(Toml::as_str) {
+                names.insert(name.to_string());
+            }
+        }
+        for entry in fs::read_dir(dir.join(folder))
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+        {
+            let path = entry.path();
+            if path.extension().and_then(|x| x.to_str()) == Some("rs") || path.join("main.rs").is_file() {
+                if let Some(stem) = path.file_stem().and_then(|x| x.to_str()) {
+                    names.insert(stem.to_string());
+                }
+            }
+        }
+        for name in names {
+            let path = dir.join(folder).join(format!("{}.rs", name));
+            add(name, kind, path);
+        }
+    }
+    targets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const REMOTE: &str = "realia-fixture-remote";
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "realia-lockfile-{}-{}-{}",
+            name,
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::canonicalize(&dir).unwrap()
+    }
+
+    fn entry(name: &str, version: &str, source: Option<&str>) -> Locked {
+        Locked {
+            name: name.to_string(),
+            version: version.to_string(),
+            source: source.map(str::to_string),
+            dependencies: vec![],
+            replace: None,
+        }
+    }
+
+    fn manifest(content: &str) -> Manifest {
+        let dir = temp_dir("manifest");
+        write(&dir.join("Cargo.toml"), content);
+        let manifest = read_manifest(&dir.join("Cargo.toml"), &dir, &empty_table())
+            .unwrap()
+            .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        manifest
+    }
+
+    #[test]
+    fn find_locked_by_each_reference_form() {
+        let git = "git+https://github.com/foo/a?branch=main#0123abcd";
+        let locked = vec![
+            entry("a", "1.0.0", Some(CRATES_IO)),
+            entry("a", "2.0.0", Some(CRATES_IO)),
+            entry("a", "2.0.0", Some(git)),
+            entry("b", "0.1.0", None),
+        ];
+
+        assert_eq!(find_locked(&locked, "b"), Some(3));
+        assert_eq!(find_locked(&locked, "a 1.0.0"), Some(0));
+        assert_eq!(find_locked(&locked, &format!("a 2.0.0 ({})", git)), Some(2));
+        assert_eq!(find_locked(&locked, &format!("a 2.0.0 ({})", CRATES_IO)), Some(1));
+        assert_eq!(find_locked(&locked, "a 3.0.0"), None);
+        assert_eq!(find_locked(&locked, "c"), None);
+
+        // Without a source, a local package wins over one with the same name and version.
+        let locked = vec![entry("c", "1.0.0", Some(CRATES_IO)), entry("c", "1.0.0", None)];
+        assert_eq!(find_locked(&locked, "c 1.0.0"), Some(1));
+        assert_eq!(find_locked(&locked, &format!("c 1.0.0 ({})", CRATES_IO)), Some(0));
+    }
+
+    #[test]
+    fn optional_dependencies_are_enabled_by_features() {
+        let manifest = manifest(
+            r#"
+            [package]
+            name = "a"
+
+            [features]
+            std = ["dep:serde", "log/std"]
+            extra = ["rand?/alloc"]
+            "#,
+        );
+        let enabled = |features: &[&str], dep: &str| {
+            let features: Vec<String> = features.iter().map(|x| x.to_string()).collect();
+            enables(&manifest, &features, dep)
+        };
+
+        assert!(enabled(&["std"], "serde"));
+        assert!(enabled(&["std"], "log"));
+        assert!(!enabled(&["std"], "rand"));
+        // A weak dependency feature doesn't enable the dependency itself.
+        assert!(!enabled(&["extra"], "rand"));
+        // Neither does a feature that isn't enabled.
+        assert!(!enabled(&[], "serde"));
+        // The dependency's implicit feature does.
+        assert!(enabled(&["rand"], "rand"));
+    }
+
+    #[test]
+    fn declarations_match_by_version() {
+        let manifest = manifest(
+            r#"
+            [package]
+            name = "a"
+
+            [dependencies]
+            rand07 = { package = "rand", version = "0.7" }
+            rand08 = { package = "rand", version = "0.8" }
+            "#,
+        );
+        let names = |version: &str| -> Vec<&str> {
+            matching_declarations(&manifest, &entry("rand", version, Some(CRATES_IO)))
+                .iter()
+                .map(|dep| dep.local_name())
+                .collect()
+        };
+
+        assert_eq!(names("0.7.3"), vec!["rand07"]);
+        assert_eq!(names("0.8.5"), vec!["rand08"]);
+        // If none of the requirements match, then any of them might be the one.
+        assert_eq!(names("0.9.0"), vec!["rand07", "rand08"]);
+        assert!(matching_declarations(&manifest, &entry("serde", "1.0.0", Some(CRATES_IO))).is_empty());
+    }
+
+    #[test]
+    fn declarations_inherit_from_the_workspace() {
+        let workspace: Toml = r#"
+            [dependencies]
+            serde = { version = "1.0", features = ["derive"], default-features = false }
+            helper = { path = "crates/helper" }
+            "#
+        .parse()
+        .unwrap();
+        let root = Path::new("/ws");
+        let dir = Path::new("/ws/app");
+        let spec = |toml: &str| -> Toml { format!("spec = {}", toml).parse::<Toml>().unwrap()["spec"].clone() };
+
+        let serde = declared(
+            "serde",
+            &spec(r#"{ workspace = true, features = ["rc"], optional = true }"#),
+            None,
+            None,
+            dir,
+            root,
+            &workspace,
+        );
+        assert_eq!(serde.name, "serde");
+        assert_eq!(serde.req, "1.0");
+        assert_eq!(serde.features, vec!["derive", "rc"]);
+        assert!(!serde.default_features);
+        assert!(serde.optional);
+        assert_eq!(serde.source.as_deref(), Some(CRATES_IO));
+
+        // Inherited paths are relative to the workspace root.
+        let helper = declared(
+            "helper",
+            &spec("{ workspace = true }"),
+            Some("dev"),
+            None,
+            dir,
+            root,
+            &workspace,
+        );
+        assert_eq!(helper.path, Some(root.join("crates/helper")));
+        assert_eq!(helper.source, None);
+        assert_eq!(helper.kind, Some("dev"));
+
+        let log = declared("log", &spec(r#""0.4""#), None, None, dir, root, &workspace);
+        assert_eq!(log.req, "0.4");
+        assert!(log.default_features);
+        assert!(!log.optional);
+        assert_eq!(log.rename, None);
+        assert_eq!(log.source.as_deref(), Some(CRATES_IO));
+
+        let renamed = declared(
+            "foo",
+            &spec(r#"{ package = "bar", git = "https://example.com/bar", branch = "main", path = "bar" }"#),
+            None,
+            Some("cfg(unix)".to_string()),
+            dir,
+            root,
+            &workspace,
+        );
+        assert_eq!(renamed.name, "bar");
+        assert_eq!(renamed.local_name(), "foo");
+        assert_eq!(renamed.req, "*");
+        assert_eq!(
+            renamed.source.as_deref(),
+            Some("git+https://example.com/bar?branch=main")
+        );
+        assert_eq!(renamed.path, Some(dir.join("bar")));
+        assert_eq!(renamed.target.as_deref(), Some("cfg(unix)"));
+    }
+
+    #[test]
+    fn metadata_is_read_from_the_lock_file() {
+        let root = temp_dir("metadata");
+        write(
+            &root.join("Cargo.toml"),
+            &format!(
+                r#"
+                [workspace]
+                members = ["crates/app", "crates/*"]
+
+                [workspace.package]
+                version = "0.3.0"
+                rust-version = "1.60"
+
+                [workspace.dependencies]
+                helper = {{ path = "helper" }}
+
+                [replace]
+                "{remote}:1.0.0" = {{ path = "remote" }}
+                "#,
+                remote = REMOTE
+            ),
+        );
+        write(
+            &root.join("crates/app/Cargo.toml"),
+            r#"
+            [package]
+            name = "app"
+            version = { workspace = true }
+            rust-version = { workspace = true }
+
+            [package.metadata.ourcorp]
+            api_level = 3
+
+            [dependencies]
+            helper = { workspace = true }
+            extra = { path = "../../extra", optional = true }
+
+            [target.'cfg(windows)'.dependencies]
+            winonly = { path = "../../winonly" }
+
+            [features]
+            default = []
+            more = ["dep:extra"]
+            "#,
+        );
+        write(&root.join("crates/app/src/lib.rs"), "");
+        write(&root.join("crates/other/Cargo.toml"), "[package]\nname = \"other\"\n");
+        for name in &["extra", "winonly"] {
+            write(
+                &root.join(name).join("Cargo.toml"),
+                &format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\n", name),
+            );
+        }
+        write(
+            &root.join("helper/Cargo.toml"),
+            &format!(
+                r#"
+                [package]
+                name = "helper"
+                version = "0.1.0"
+                links = "helper"
+
+                [dependencies]
+                {remote} = "1.0"
+                remote2 = {{ package = "{remote}", version = "2.0" }}
+                "#,
+                remote = REMOTE
+            ),
+        );
+        write(
+            &root.join("remote/Cargo.toml"),
+            &format!("[package]\nname = \"{}\"\nversion = \"1.0.0\"\n", REMOTE),
+        );
+        write(
+            &root.join("Cargo.lock"),
+            &format!(
+                r#"
+                version = 3
+
+                [[package]]
+                name = "app"
+                version = "0.3.0"
+                dependencies = [
+                 "extra",
+                 "helper",
+                 "winonly",
+                ]
+
+                [[package]]
+                name = "extra"
+                version = "0.1.0"
+
+                [[package]]
+                name = "helper"
+                version = "0.1.0"
+                dependencies = [
+                 "{remote} 1.0.0 ({registry})",
+                 "{remote} 2.0.0",
+                ]
+
+                [[package]]
+                name = "other"
+                version = "0.0.0"
+
+                [[package]]
+                name = "{remote}"
+                version = "1.0.0"
+                source = "{registry}"
+                replace = "{remote} 1.0.0"
+
+                [[package]]
+                name = "{remote}"
+                version = "1.0.0"
+
+                [[package]]
+                name = "{remote}"
+                version = "2.0.0"
+                source = "{registry}"
+                dependencies = [
+                 "extra",
+                ]
+
+                [[package]]
+                name = "winonly"
+                version = "0.1.0"
+                "#,
+                remote = REMOTE,
+                registry = CRATES_IO
+            ),
+        );
+
+        let read = |features: Option<&[&str]>| -> Value {
+            let query = Query {
+                manifest_dir: root.join("crates/app").to_string_lossy().into_owned(),
+                target: "x86_64-unknown-linux-gnu".to_string(),
+                features: features.map(|x| x.iter().map(|x| x.to_string()).collect()),
+            };
+            serde_json::from_str(&read_metadata(&query).unwrap()).unwrap()
+        };
+        let local_id = |name: &str, version: &str, dir: &str| {
+            format!("{} {} (path+file://{})", name, version, root.join(dir).display())
+        };
+        let app = local_id("app", "0.3.0", "crates/app");
+        let extra = local_id("extra", "0.1.0", "extra");
+        let helper = local_id("helper", "0.1.0", "helper");
+        let replaced = local_id(REMOTE, "1.0.0", "remote");
+        let remote2 = format!("{} 2.0.0 ({})", REMOTE, CRATES_IO);
+
+        let metadata = read(Some(&["default"]));
+        assert_eq!(metadata["resolve"]["root"], json!(app));
+        assert!(metadata["workspace_members"].as_array().unwrap().contains(&json!(app)));
+
+        let find = |list: &Value, id: &str| {
+            list.as_array()
+                .unwrap()
+                .iter()
+                .find(|x| x["id"] == id)
+                .cloned()
+                .unwrap()
+        };
+        let package = |id: &str| find(&metadata["packages"], id);
+        let node = |id: &str| find(&metadata["resolve"]["nodes"], id);
+
+        let app_package = package(&app);
+        assert_eq!(app_package["rust_version"], "1.60");
+        assert_eq!(app_package["metadata"]["ourcorp"]["api_level"], 3);
+        assert_eq!(app_package["targets"][0]["kind"], json!(["lib"]));
+        assert_eq!(app_package["realia_unknown"], json!([]));
+        assert_eq!(package(&helper)["links"], "helper");
+        // The replaced package is reported as its replacement.
+        assert!(metadata["packages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|x| x["id"] != format!("{} 1.0.0 ({})", REMOTE, CRATES_IO)));
+        assert_eq!(package(&replaced)["realia_unknown"], json!(["features"]));
+
+        // The manifests of registry packages aren't read, even if one happens to be around.
+        let remote_package = package(&remote2);
+        assert_eq!(remote_package["source"], CRATES_IO);
+        assert_eq!(remote_package["dependencies"], json!([]));
+        assert_eq!(remote_package["realia_unknown"], json!(UNREAD));
+        // Neither are those of members that are only listed with a wildcard.
+        assert_eq!(package("other 0.0.0")["realia_unknown"], json!(UNREAD));
+
+        // The disabled optional dependency and the dependency for another target are left out.
+        assert_eq!(node(&app)["dependencies"], json!([helper]));
+        assert_eq!(node(&app)["features"], json!(["default"]));
+        let helper_deps = &node(&helper)["deps"];
+        assert_eq!(helper_deps[0]["name"], REMOTE.replace('-', "_"));
+        assert_eq!(helper_deps[0]["pkg"], json!(replaced));
+        assert_eq!(helper_deps[1]["name"], "remote2");
+        assert_eq!(helper_deps[1]["pkg"], json!(remote2));
+        // The features of other packages are unknown.
+        assert_eq!(node(&helper)["features"], json!([]));
+        // The edges of registry packages are taken from the lock file as-is.
+        assert_eq!(node(&remote2)["dependencies"], json!([extra]));
+
+        // Enabling the feature pulls in the optional dependency.
+        let metadata = read(Some(&["more"]));
+        let app_node = find(&metadata["resolve"]["nodes"], &app);
+        assert_eq!(app_node["dependencies"], json!([extra, helper]));
+        assert_eq!(app_node["features"], json!(["more"]));
+
+        // Without the features, the optional dependency might be used or not.
+        let metadata = read(None);
+        let app_node = find(&metadata["resolve"]["nodes"], &app);
+        assert_eq!(app_node["dependencies"], json!([extra, helper]));
+        assert_eq!(
+            find(&metadata["packages"], &app)["realia_unknown"],
+            json!(["features", "optional"])
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -4,12 +4,68 @@
 use anyhow::{anyhow, Context, Error};
 use cargo_metadata::Metadata;
 use std::{
+    collections::HashMap,
     env,
     ffi::OsString,
+    fs,
+    hash::Hash,
+    path::Path,
     process::{Command, Stdio},
+    sync::{Arc, Mutex},
+    time::SystemTime,
 };
 
-fn check_arg(name: &str) -> Option<String> {
+/// A process-wide map of memoized values, along with the [`Modified`] times
+/// that each one was computed for. See [`memoize`].
+pub type Cache<K, V> = Mutex<Option<HashMap<K, (Modified, V)>>>;
+
+/// When the manifest and lock file were last modified. A long-lived process like
+/// rust-analyzer's proc macro server keeps memoized values around between
+/// expansions, so this makes it notice when those files change.
+pub type Modified = Vec<Option<SystemTime>>;
+
+/// Cache of `cargo metadata` results, along with the `rust-version` of each package.
+///
+/// The proc macro library stays loaded for the whole compilation of a crate,
+/// so this lets every attribute in that crate share a single `cargo metadata` call.
+static METADATA: Cache<Query, Snapshot> = Mutex::new(None);
+
+/// Cache of the output of `rustc -Vv`.
+static RUSTC_INFO: Cache<(), String> = Mutex::new(None);
+
+/// The `rust-version` of each package that declares one, keyed by package ID.
+/// This version of `cargo_metadata` doesn't parse that field, so it's read separately.
+pub type RustVersions = HashMap<String, String>;
+
+/// What the fallback for `cargo metadata` couldn't determine about each package,
+/// by field name and keyed by package ID. This is empty when Cargo was run.
+pub type Unknowns = HashMap<String, Vec<String>>;
+
+type Snapshot = (Arc<Metadata>, Arc<RustVersions>, Arc<Unknowns>);
+
+/// Looks up `key` in `cache`, computing and storing the value on a miss or if the
+/// stored value was computed before the files were `modified`. A stale value is
+/// replaced rather than kept alongside the new one, so the cache doesn't grow with
+/// every edit. Errors are not cached, so a later invocation can try again.
+pub fn memoize<K, V, F>(cache: &Cache<K, V>, key: K, modified: Modified, compute: F) -> Result<V, Error>
+where
+    K: Eq + Hash,
+    V: Clone,
+    F: FnOnce() -> Result<V, Error>,
+{
+    let mut guard = cache.lock().unwrap_or_else(|e| e.into_inner());
+    let map = guard.get_or_insert_with(HashMap::new);
+    if let Some((when, value)) = map.get(&key) {
+        if *when == modified {
+            return Ok(value.clone());
+        }
+    }
+    let value = compute()?;
+    map.insert(key, (modified, value.clone()));
+    Ok(value)
+}
+
+pub fn check_arg(name: &str) -> Option<String> {
     let mut args = env::args().skip_while(|val| !val.starts_with(name));
     match args.next() {
         // Get `bar` from `--foo bar`:
@@ -20,7 +76,126 @@ fn check_arg(name: &str) -> Option<String> {
     }
 }
 
-pub fn get_metadata() -> Result<Metadata, Error> {
+/// Everything that determines the output of `cargo metadata` for the current compilation.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Query {
+    pub manifest_dir: String,
+    pub target: String,
+    /// The features enabled on the crate being compiled,
+    /// or `None` if they can't be determined.
+    pub features: Option<Vec<String>>,
+}
+
+impl Query {
+    pub fn current() -> Result<Self, Error> {
+        let manifest_dir = get_manifest_dir()?;
+        Ok(Query {
+            manifest_dir,
+            target: get_target(),
+            features: get_features(),
+        })
+    }
+
+    /// Gets the modification times of the manifest, along with the lock file
+    /// and workspace manifest next to it.
+    pub fn modified(&self) -> Modified {
+        let modified = |path: &Path| fs::metadata(path).and_then(|x| x.modified()).ok();
+        let manifest_dir = Path::new(&self.manifest_dir);
+        let mut times = vec![modified(&manifest_dir.join("Cargo.toml"))];
+        if let Some(lock_dir) = manifest_dir.ancestors().find(|dir| dir.join("Cargo.lock").is_file()) {
+            times.push(modified(&lock_dir.join("Cargo.lock")));
+            times.push(modified(&lock_dir.join("Cargo.toml")));
+        }
+        times
+    }
+}
+
+pub fn get_metadata() -> Result<Arc<Metadata>, Error> {
+    get_snapshot().map(|(metadata, _, _)| metadata)
+}
+
+pub fn get_rust_versions() -> Result<Arc<RustVersions>, Error> {
+    get_snapshot().map(|(_, rust_versions, _)| rust_versions)
+}
+
+pub fn get_unknowns() -> Result<Arc<Unknowns>, Error> {
+    get_snapshot().map(|(_, _, unknowns)| unknowns)
+}
+
+fn get_snapshot() -> Result<Snapshot, Error> {
+    let query = Query::current()?;
+    memoize(&METADATA, query.clone(), query.modified(), || {
+        let (metadata, rust_versions, unknowns) = fetch_metadata(&query)?;
+        Ok((Arc::new(metadata), Arc::new(rust_versions), Arc::new(unknowns)))
+    })
+}
+
+fn fetch_metadata(query: &Query) -> Result<(Metadata, RustVersions, Unknowns), Error> {
+    let cache_dir = crate::cache::dir();
+    if let Some(cached) = cache_dir.as_ref().and_then(|dir| crate::cache::load(dir, query)) {
+        if let Ok(parsed) = parse_snapshot(cached) {
+            return Ok(parsed);
+        }
+    }
+
+    let output = match run_cargo_metadata(query) {
+        Ok(output) => output,
+        Err(e) => return fall_back(query, e),
+    };
+    let parsed = parse_metadata(&output).context("error parsing cargo metadata output")?;
+    if let Some(dir) = &cache_dir {
+        crate::cache::store(dir, query, &output);
+    }
+    Ok(parsed)
+}
+
+/// Reads `Cargo.lock` and the local manifests directly when `cargo metadata` can't be run.
+/// The result isn't saved to the cache, so the next build will try Cargo again.
+fn fall_back(query: &Query, error: Error) -> Result<(Metadata, RustVersions, Unknowns), Error> {
+    let output = crate::lockfile::read_metadata(query)
+        .map_err(|fallback| anyhow!("{:#} (and reading Cargo.lock instead failed: {:#})", error, fallback))?;
+    let parsed = parse_metadata(&output).context("error parsing Cargo.lock")?;
+    crate::diagnostic::warn(
+        proc_macro2::Span::call_site(),
+        format!(
+            "realia: {:#}, so dependency checks are based on Cargo.lock and the local Cargo.toml files instead, \
+             and any check that needs more will fail",
+            error
+        ),
+    );
+    Ok(parsed)
+}
+
+fn parse_metadata(output: &str) -> Result<(Metadata, RustVersions, Unknowns), Error> {
+    parse_snapshot(serde_json::from_str(output)?)
+}
+
+fn parse_snapshot(value: serde_json::Value) -> Result<(Metadata, RustVersions, Unknowns), Error> {
+    let mut rust_versions = HashMap::new();
+    let mut unknowns = HashMap::new();
+    for package in value["packages"].as_array().into_iter().flatten() {
+        let id = match package["id"].as_str() {
+            Some(id) => id,
+            None => continue,
+        };
+        if let Some(rust_version) = package["rust_version"].as_str() {
+            rust_versions.insert(id.to_string(), rust_version.to_string());
+        }
+        // Only the fallback in `lockfile` reports this.
+        let unknown: Vec<String> = package["realia_unknown"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|x| x.as_str().map(str::to_string))
+            .collect();
+        if !unknown.is_empty() {
+            unknowns.insert(id.to_string(), unknown);
+        }
+    }
+    Ok((serde_json::from_value(value)?, rust_versions, unknowns))
+}
+
+fn run_cargo_metadata(query: &Query) -> Result<String, Error> {
     let cargo = env::var_os("CARGO").unwrap_or_else(|| OsString::from("cargo"));
 
     let mut command = Command::new(cargo);
@@ -28,21 +203,19 @@ pub fn get_metadata() -> Result<Metadata, Error> {
     command.arg("--format-version").arg("1");
     command
         .arg("--manifest-path")
-        .arg(format!("{}/Cargo.toml", get_manifest_dir()?));
+        .arg(format!("{}/Cargo.toml", query.manifest_dir));
+    command.arg("--filter-platform").arg(&query.target);
 
-    // Map from rustc arguments to Cargo arguments.
-    match check_arg("--target") {
-        Some(v) => {
-            command.arg("--filter-platform").arg(v);
-        }
-        _ => {
-            command.arg("--filter-platform").arg(default_target()?);
+    // Resolve with the same features as the current compilation,
+    // so that optional dependencies are only included when enabled.
+    if let Some(features) = &query.features {
+        command.arg("--no-default-features");
+        if !features.is_empty() {
+            command.arg("--features").arg(features.join(","));
         }
-    };
-
-    let output = output(&mut command, "cargo metadata")?;
+    }
 
-    serde_json::from_str(&output).context("error parsing cargo metadata output")
+    output(&mut command, "cargo metadata")
 }
 
 pub fn get_manifest_dir() -> Result<String, Error> {
@@ -52,18 +225,81 @@ pub fn get_manifest_dir() -> Result<String, Error> {
     }
 }
 
-pub fn default_target() -> Result<String, Error> {
-    let rustc = env::var_os("RUSTC").unwrap_or_else(|| OsString::from("rustc"));
-    let output = output(Command::new(rustc).arg("-Vv"), "rustc")?;
+/// The target being compiled for, mapped from rustc's `--target` argument,
+/// or the host when there isn't one.
+pub fn get_target() -> String {
+    check_arg("--target").unwrap_or_else(default_target)
+}
+
+/// The features enabled on the crate being compiled, mapped from rustc's
+/// `--cfg feature="..."` arguments. This includes `default` when applicable,
+/// along with everything that the enabled features pull in.
+pub fn get_features() -> Option<Vec<String>> {
+    let args: Vec<String> = env::args().collect();
+    // Outside of rustc (e.g., in an IDE), the arguments don't tell us anything.
+    if !args
+        .iter()
+        .any(|arg| arg == "--crate-name" || arg.starts_with("--crate-name="))
+    {
+        return None;
+    }
+
+    let mut features = vec![];
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let cfg = match arg.strip_prefix("--cfg") {
+            Some("") => iter.next().map(String::as_str),
+            Some(v) => v.strip_prefix('='),
+            None => None,
+        };
+        if let Some(feature) = cfg
+            .and_then(|x| x.strip_prefix("feature=\""))
+            .and_then(|x| x.strip_suffix('"'))
+        {
+            features.push(feature.to_string());
+        }
+    }
+    features.sort();
+    Some(features)
+}
+
+/// The name of the crate being compiled, mapped from rustc's `--crate-name` argument.
+/// Build scripts are compiled as `build_script_build` (or similar).
+pub fn get_crate_name() -> Option<String> {
+    check_arg("--crate-name")
+}
+
+/// Whether the crate is being compiled as a test harness,
+/// which is how Cargo builds unit tests, integration tests, and benchmarks.
+pub fn is_test() -> bool {
+    env::args().any(|arg| arg == "--test")
+}
+
+/// The host target according to rustc, or else the host that Realia was built for
+/// (which is the same one, since proc macros run where they're built).
+fn default_target() -> String {
+    rustc_info("host").unwrap_or_else(|_| env!("REALIA_HOST_TARGET").to_string())
+}
+
+/// The version of the active Rust compiler, like `1.70.0` or `1.72.0-nightly`.
+pub fn rustc_version() -> Result<String, Error> {
+    rustc_info("release")
+}
+
+/// Finds a field in the output of `rustc -Vv`.
+fn rustc_info(field: &str) -> Result<String, Error> {
+    let output = memoize(&RUSTC_INFO, (), vec![], || {
+        let rustc = env::var_os("RUSTC").unwrap_or_else(|| OsString::from("rustc"));
+        output(Command::new(rustc).arg("-Vv"), "rustc")
+    })?;
 
     for line in output.lines() {
-        let prefix = "host: ";
-        if let Some(stripped) = line.strip_prefix(prefix) {
+        if let Some(stripped) = line.strip_prefix(field).and_then(|x| x.strip_prefix(": ")) {
             return Ok(stripped.trim().to_string());
         }
     }
 
-    Err(anyhow!("host missing from rustc output"))
+    Err(anyhow!("{} missing from rustc output", field))
 }
 
 fn output(command: &mut Command, job: &str) -> Result<String, Error> {
@@ -0,0 +1,438 @@
+//! Parsing of the package sources reported by `cargo metadata`.
+//!
+//! Cargo identifies sources by strings like `registry+https://github.com/rust-lang/crates.io-index`,
+//! `sparse+https://my-corp.example/index/`, or `git+https://github.com/foo/bar?branch=dev#abc123`.
+//! Path dependencies don't have a source at all.
+
+use crate::metadata::{Cache, Query};
+use anyhow::Error;
+use std::{
+    env, fs,
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use syn::LitStr;
+
+/// Cache of the registries configured for Cargo, keyed by the metadata query.
+static REGISTRIES: Cache<Query, Arc<Vec<(String, String)>>> = Mutex::new(None);
+
+/// The index URLs that Cargo uses for crates.io.
+const CRATES_IO: &[&str] = &[
+    "https://github.com/rust-lang/crates.io-index",
+    "https://index.crates.io",
+];
+
+pub enum Source {
+    Path,
+    Git(GitSource),
+    /// A registry, with the URL of its index.
+    Registry(String),
+    /// Anything that Cargo doesn't normally report, like a local registry.
+    Other,
+}
+
+impl Source {
+    pub fn parse(source: Option<&str>) -> Self {
+        let source = match source {
+            Some(source) => source,
+            None => return Source::Path,
+        };
+        if let Some(git) = source.strip_prefix("git+") {
+            Source::Git(GitSource::parse(git))
+        } else if let Some(index) = source
+            .strip_prefix("registry+")
+            .or_else(|| source.strip_prefix("sparse+"))
+        {
+            Source::Registry(normalize_index(index))
+        } else {
+            Source::Other
+        }
+    }
+}
+
+/// The reference and commit of a Git source, like
+/// `https://github.com/foo/bar?branch=dev#0123456789abcdef0123456789abcdef01234567`.
+/// The query says what the manifest asked for (if anything other than the default branch),
+/// and the fragment is the commit that it was resolved to.
+pub struct GitSource {
+    pub branch: Option<String>,
+    pub tag: Option<String>,
+    pub rev: Option<String>,
+    pub commit: Option<String>,
+}
+
+impl GitSource {
+    fn parse(source: &str) -> Self {
+        let (rest, commit) = match source.split_once('#') {
+            Some((rest, commit)) => (rest, Some(commit.to_string())),
+            None => (source, None),
+        };
+        let mut git = GitSource {
+            branch: None,
+            tag: None,
+            rev: None,
+            commit,
+        };
+        let query = rest.split_once('?').map(|(_, query)| query).unwrap_or_default();
+        for pair in query.split('&') {
+            let (key, value) = match pair.split_once('=') {
+                Some((key, value)) => (key, percent_decode(value)),
+                None => continue,
+            };
+            match key {
+                "branch" => git.branch = Some(value),
+                "tag" => git.tag = Some(value),
+                "rev" => git.rev = Some(value),
+                _ => {}
+            }
+        }
+        git
+    }
+
+    /// Checks if this is the given revision, which may be exactly what the manifest
+    /// specified as `rev` (any ref that Git accepts) or an abbreviated commit hash.
+    pub fn is_rev(&self, rev: &str) -> bool {
+        if self.rev.as_deref() == Some(rev) {
+            return true;
+        }
+        // Git itself won't abbreviate a commit to fewer than 4 characters,
+        // and a shorter prefix would match far too many commits.
+        let rev = rev.to_ascii_lowercase();
+        if rev.len() < 4 || !rev.chars().all(|c| c.is_ascii_hexdigit()) {
+            return false;
+        }
+        self.rev.iter().any(|x| x.to_ascii_lowercase() == rev)
+            || self.commit.iter().any(|commit| commit.starts_with(&rev))
+    }
+}
+
+/// Decodes `%XX` escapes, which Cargo uses for characters like `/` in branch names.
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// A source argument, validated when the attribute is parsed.
+pub struct SourceArg {
+    pub lit: LitStr,
+    pub value: Expected,
+}
+
+pub enum Expected {
+    Path,
+    Git,
+    /// Any registry, or a specific one by name.
+    Registry(Option<String>),
+    /// A registry or Git dependency that Cargo loads from a local directory instead,
+    /// as with `cargo vendor`.
+    Vendored,
+}
+
+impl SourceArg {
+    pub fn new(lit: LitStr) -> syn::Result<Self> {
+        let value = match lit.value().as_str() {
+            "path" => Expected::Path,
+            "git" => Expected::Git,
+            "registry" => Expected::Registry(None),
+            "vendored" => Expected::Vendored,
+            other => match other.strip_prefix("registry:") {
+                Some(name) if !name.is_empty() => Expected::Registry(Some(name.to_string())),
+                _ => {
+                    return Err(syn::Error::new(
+                        lit.span(),
+                        format!(
+                            "expected \"path\", \"git\", \"registry\", \"registry:<name>\", or \"vendored\", \
+                             but found '{}'",
+                            other
+                        ),
+                    ))
+                }
+            },
+        };
+        Ok(SourceArg { lit, value })
+    }
+}
+
+/// Strips the parts of an index URL that don't affect which registry it refers to.
+fn normalize_index(index: &str) -> String {
+    let index = index.strip_prefix("sparse+").unwrap_or(index);
+    index.trim_end_matches('/').to_string()
+}
+
+/// Finds the index URLs of a registry by the name used for it in Cargo's configuration.
+/// There may be several for crates.io, which can be accessed in different ways.
+pub fn registry_indices(name: &str) -> Result<Option<Vec<String>>, Error> {
+    if name == "crates-io" {
+        return Ok(Some(CRATES_IO.iter().map(|x| x.to_string()).collect()));
+    }
+    Ok(get_registries()?
+        .iter()
+        .find(|(registry, _)| registry == name)
+        .map(|(_, index)| vec![index.clone()]))
+}
+
+/// Lists the names of the registries configured for Cargo.
+pub fn registry_names() -> Result<Vec<String>, Error> {
+    let mut names: Vec<String> = get_registries()?.iter().map(|(name, _)| name.clone()).collect();
+    names.push("crates-io".to_string());
+    Ok(names)
+}
+
+fn get_registries() -> Result<Arc<Vec<(String, String)>>, Error> {
+    let query = Query::current()?;
+    crate::metadata::memoize(&REGISTRIES, query.clone(), query.modified(), || {
+        Ok(Arc::new(find_registries(&query.manifest_dir)))
+    })
+}
+
+/// Reads the `[registries]` from Cargo's configuration files and environment variables,
+/// ordered by precedence: environment variables first, then the configuration files
+/// from the closest to the farthest, and finally the one in Cargo's home folder.
+fn find_registries(manifest_dir: &str) -> Vec<(String, String)> {
+    let mut registries = vec![];
+
+    for (key, value) in env::vars() {
+        if let Some(name) = key
+            .strip_prefix("CARGO_REGISTRIES_")
+            .and_then(|x| x.strip_suffix("_INDEX"))
+        {
+            // Environment variables can't contain hyphens, so this accepts either spelling.
+            let name = name.to_lowercase();
+            registries.push((name.replace('_', "-"), normalize_index(&value)));
+            registries.push((name, normalize_index(&value)));
+        }
+    }
+
+    for (_, config) in config_files(manifest_dir) {
+        let tables = config.get("registries").and_then(toml::Value::as_table);
+        for (name, registry) in tables.into_iter().flatten() {
+            if let Some(index) = registry.get("index").and_then(toml::Value::as_str) {
+                registries.push((name.clone(), normalize_index(index)));
+            }
+        }
+    }
+
+    registries
+}
+
+/// Finds Cargo's home folder, where it keeps downloaded packages and global configuration.
+pub fn cargo_home() -> Option<PathBuf> {
+    env::var_os("CARGO_HOME").map(Into::into).or_else(|| {
+        env::var_os("HOME")
+            .or_else(|| env::var_os("USERPROFILE"))
+            .map(|home| Path::new(&home).join(".cargo"))
+    })
+}
+
+/// Reads Cargo's configuration files, from the closest to `manifest_dir` to the farthest,
+/// and finally the one in Cargo's home folder. Each comes with the folder that relative
+/// paths in it are based on, which is the parent of the `.cargo` folder.
+pub fn config_files(manifest_dir: &str) -> Vec<(PathBuf, toml::Value)> {
+    let config_dirs = Path::new(manifest_dir)
+        .ancestors()
+        .map(|dir| dir.join(".cargo"))
+        .chain(cargo_home());
+    let mut configs = vec![];
+    for dir in config_dirs {
+        for file in &["config.toml", "config"] {
+            if let Some(config) = fs::read_to_string(dir.join(file))
+                .ok()
+                .and_then(|x| x.parse::<toml::Value>().ok())
+            {
+                let base = dir.parent().unwrap_or(&dir).to_path_buf();
+                configs.push((base, config));
+            }
+        }
+    }
+    configs
+}
+
+/// Checks if a package was resolved from a different source than the one declared
+/// for it, which happens when it's overridden by `[patch]` or `[replace]`.
+/// For both of those, `cargo metadata` reports the overriding package in the graph,
+/// while the manifest's dependency keeps the original source.
+/// The commit of a Git source is ignored, since the declaration doesn't have one.
+pub fn is_patched(declared: Option<&str>, resolved: Option<&str>) -> bool {
+    let without_commit = |source: Option<&str>| source.map(|x| x.split('#').next().unwrap_or(x).to_string());
+    if without_commit(declared) == without_commit(resolved) {
+        return false;
+    }
+    match (Source::parse(declared), Source::parse(resolved)) {
+        (Source::Registry(a), Source::Registry(b)) => {
+            a != b && !(CRATES_IO.contains(&a.as_str()) && CRATES_IO.contains(&b.as_str()))
+        }
+        _ => true,
+    }
+}
+
+/// Checks if a package that was downloaded from a registry or Git repository
+/// is actually loaded from somewhere else, as with `cargo vendor`.
+/// Normally, Cargo unpacks these under `registry/src` or `git/checkouts`
+/// in its home folder.
+pub fn is_vendored(source: &Source, manifest_path: &Path) -> bool {
+    let expected: &[&str] = match source {
+        Source::Registry(_) => &["registry", "src"],
+        Source::Git(_) => &["git", "checkouts"],
+        _ => return false,
+    };
+    let components: Vec<&str> = manifest_path
+        .components()
+        .filter_map(|x| match x {
+            Component::Normal(x) => x.to_str(),
+            _ => None,
+        })
+        .collect();
+    !components.windows(2).any(|pair| pair == expected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry(source: &str) -> Option<String> {
+        match Source::parse(Some(source)) {
+            Source::Registry(index) => Some(index),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn parse_registry_sources() {
+        assert_eq!(
+            registry("registry+https://github.com/rust-lang/crates.io-index").as_deref(),
+            Some("https://github.com/rust-lang/crates.io-index")
+        );
+        assert_eq!(
+            registry("sparse+https://index.crates.io/").as_deref(),
+            Some("https://index.crates.io")
+        );
+        assert_eq!(
+            registry("sparse+https://my-corp.example/index/").as_deref(),
+            Some("https://my-corp.example/index")
+        );
+        assert!(matches!(Source::parse(None), Source::Path));
+        assert!(matches!(
+            Source::parse(Some("git+https://github.com/foo/bar#0123abcd")),
+            Source::Git(_)
+        ));
+        assert!(matches!(
+            Source::parse(Some("local-registry+file:///registry")),
+            Source::Other
+        ));
+    }
+
+    const COMMIT: &str = "0123456789abcdef0123456789abcdef01234567";
+
+    fn git(source: &str) -> GitSource {
+        match Source::parse(Some(source)) {
+            Source::Git(git) => git,
+            _ => panic!("not a Git source: {}", source),
+        }
+    }
+
+    #[test]
+    fn parse_git_sources() {
+        let branch = git(&format!("git+https://github.com/foo/bar?branch=feat%2Fx#{}", COMMIT));
+        assert_eq!(branch.branch.as_deref(), Some("feat/x"));
+        assert_eq!(branch.tag, None);
+        assert_eq!(branch.rev, None);
+        assert_eq!(branch.commit.as_deref(), Some(COMMIT));
+
+        let tag = git(&format!("git+https://github.com/foo/bar?tag=v1.0#{}", COMMIT));
+        assert_eq!(tag.tag.as_deref(), Some("v1.0"));
+        assert_eq!(tag.branch, None);
+
+        let rev = git(&format!("git+https://github.com/foo/bar?rev=0123abcd#{}", COMMIT));
+        assert_eq!(rev.rev.as_deref(), Some("0123abcd"));
+
+        // The default branch, and a declared source that hasn't been resolved to a commit.
+        let default = git(&format!("git+https://github.com/foo/bar#{}", COMMIT));
+        assert_eq!((default.branch, default.tag, default.rev), (None, None, None));
+        let declared = git("git+https://github.com/foo/bar?branch=dev");
+        assert_eq!(declared.branch.as_deref(), Some("dev"));
+        assert_eq!(declared.commit, None);
+    }
+
+    #[test]
+    fn git_is_rev() {
+        let source = git(&format!("git+https://github.com/foo/bar?rev=0123abcd#{}", COMMIT));
+        assert!(source.is_rev("0123"));
+        assert!(source.is_rev("0123456789ABCDEF"));
+        // The manifest's `rev` doesn't have to be a prefix of the resolved commit,
+        // e.g. if it was a commit on a branch that has been rebased.
+        assert!(source.is_rev("0123abcd"));
+        assert!(source.is_rev("0123ABCD"));
+        assert!(source.is_rev(COMMIT));
+        assert!(!source.is_rev("1234"));
+        assert!(!source.is_rev(&format!("{}0", COMMIT)));
+        // Short or non-hex values can only match the manifest's `rev` exactly.
+        assert!(!source.is_rev("012"));
+        assert!(!source.is_rev("refs/heads/main"));
+
+        let named = git(&format!(
+            "git+https://github.com/foo/bar?rev=refs%2Fheads%2FMain#{}",
+            COMMIT
+        ));
+        assert!(named.is_rev("refs/heads/Main"));
+        assert!(!named.is_rev("refs/heads/main"));
+        assert!(named.is_rev("0123"));
+
+        let unresolved = git("git+https://github.com/foo/bar?branch=dev");
+        assert!(!unresolved.is_rev("0123"));
+    }
+
+    #[test]
+    fn decode_percent_escapes() {
+        assert_eq!(percent_decode("feat%2Fx"), "feat/x");
+        assert_eq!(percent_decode("a%20b%2fc"), "a b/c");
+        assert_eq!(percent_decode("plain"), "plain");
+        // Invalid or truncated escapes are kept as they are.
+        assert_eq!(percent_decode("100%zz"), "100%zz");
+        assert_eq!(percent_decode("trailing%2"), "trailing%2");
+        assert_eq!(percent_decode("%"), "%");
+    }
+
+    #[test]
+    fn patched_sources() {
+        let crates_io = Some("registry+https://github.com/rust-lang/crates.io-index");
+        let git = "git+https://github.com/foo/bar?branch=dev";
+
+        // A registry dependency patched or replaced with a local path.
+        assert!(is_patched(crates_io, None));
+        // A registry dependency patched with a Git repository.
+        assert!(is_patched(crates_io, Some(&format!("{}#{}", git, COMMIT))));
+        // A registry dependency patched with another registry.
+        assert!(is_patched(crates_io, Some("sparse+https://my-corp.example/index/")));
+        // A Git dependency patched with a different branch.
+        assert!(is_patched(
+            Some(git),
+            Some(&format!("git+https://github.com/foo/bar?branch=main#{}", COMMIT))
+        ));
+
+        // Different ways of accessing crates.io are the same source.
+        assert!(!is_patched(crates_io, Some("sparse+https://index.crates.io/")));
+        assert!(!is_patched(Some("sparse+https://index.crates.io/"), crates_io));
+        // A Git source is resolved to a commit, which the declaration doesn't have.
+        assert!(!is_patched(Some(git), Some(&format!("{}#{}", git, COMMIT))));
+        assert!(!is_patched(crates_io, crates_io));
+        assert!(!is_patched(None, None));
+    }
+}
@@ -0,0 +1,69 @@
+use syn::{parse::Result, Error, LitStr};
+
+/// A version argument, validated when the attribute is parsed.
+///
+/// Like in `Cargo.toml`, the minor and patch numbers may be omitted,
+/// so `1` is treated as `1.0.0` and `1.2` as `1.2.0`.
+pub struct Version {
+    pub lit: LitStr,
+    pub value: semver::Version,
+}
+
+impl Version {
+    pub fn new(lit: LitStr) -> Result<Self> {
+        match parse_version(&lit.value()) {
+            Some(value) => Ok(Version { lit, value }),
+            None => Err(Error::new(
+                lit.span(),
+                format!("expected a version like 1.2.3, but found '{}'", lit.value()),
+            )),
+        }
+    }
+}
+
+/// A version requirement argument, using the same syntax as `Cargo.toml`
+/// (e.g., `^1.2`, `~1.2.3`, or `>=1.2, <1.5`).
+pub struct Requirement {
+    pub lit: LitStr,
+    pub value: semver::VersionReq,
+}
+
+impl Requirement {
+    pub fn new(lit: LitStr) -> Result<Self> {
+        match semver::VersionReq::parse(&lit.value()) {
+            Ok(value) => Ok(Requirement { lit, value }),
+            Err(e) => Err(Error::new(
+                lit.span(),
+                format!(
+                    "expected a version requirement like ^1.2.3 or >=1.2, <1.5, but found '{}' ({})",
+                    lit.value(),
+                    e
+                ),
+            )),
+        }
+    }
+}
+
+pub fn parse_version(raw: &str) -> Option<semver::Version> {
+    let raw = raw.trim();
+    let (release, rest) = match raw.find(['-', '+']) {
+        Some(i) => raw.split_at(i),
+        None => (raw, ""),
+    };
+
+    let mut numbers = vec![];
+    for part in release.split('.') {
+        if part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        numbers.push(part);
+    }
+    if numbers.len() > 3 {
+        return None;
+    }
+    while numbers.len() < 3 {
+        numbers.push("0");
+    }
+
+    semver::Version::parse(&format!("{}{}", numbers.join("."), rest)).ok()
+}
@@ -64,6 +64,38 @@ fn dep_exists() {
     assert!(negative());
 }
 
+#[test]
+#[allow(deprecated)] // The anchor is the fixture rather than the crate being compiled.
+fn dep_exists_optional() {
+    #[realia::not(any(
+        dep("realia-optional-fixture", "ryu"),
+        dep("realia-optional-fixture", "ryu", "1.0.0"),
+        dep_since("realia-optional-fixture", "ryu", "1.0.0"),
+        dep_from_registry("realia-optional-fixture", "ryu"),
+        dep_source("realia-optional-fixture", "ryu", "registry"),
+        dep_transitive("realia-optional-fixture", "ryu"),
+    ))]
+    fn negative() -> bool {
+        true
+    }
+    assert!(negative());
+}
+
+#[test]
+fn dep_exists_inferred_anchor() {
+    #[realia::dep("syn")]
+    fn positive() -> bool {
+        true
+    }
+    assert!(positive());
+
+    #[realia::not(dep("reqwest"))]
+    fn negative() -> bool {
+        true
+    }
+    assert!(negative());
+}
+
 #[test]
 fn dep_equals() {
     #[realia::dep("realia", "version-compare", "0.0.10")]
@@ -77,6 +109,12 @@ fn dep_equals() {
         true
     }
     assert!(negative());
+
+    #[realia::not(dep("realia", "version-compare", "0.0.10+build"))]
+    fn negative_build() -> bool {
+        true
+    }
+    assert!(negative_build());
 }
 
 #[test]
@@ -94,6 +132,39 @@ fn dep_since() {
     assert!(negative());
 }
 
+#[test]
+fn dep_since_prerelease() {
+    #[realia::all(
+        dep_since("realia", "syn", "1.0.0-alpha"),
+        dep_since("realia", "syn", "1.0.0-alpha", prerelease = false)
+    )]
+    fn positive() -> bool {
+        true
+    }
+    assert!(positive());
+
+    #[realia::not(dep_before("realia", "syn", "1.0.0-alpha"))]
+    fn negative() -> bool {
+        true
+    }
+    assert!(negative());
+}
+
+#[test]
+fn dep_since_inferred_anchor() {
+    #[realia::dep_since("syn", "1.0")]
+    fn positive() -> bool {
+        true
+    }
+    assert!(positive());
+
+    #[realia::not(dep_since("syn", "999"))]
+    fn negative() -> bool {
+        true
+    }
+    assert!(negative());
+}
+
 #[test]
 fn dep_before() {
     #[realia::dep_before("realia", "syn", "999")]
@@ -109,6 +180,32 @@ fn dep_before() {
     assert!(negative());
 }
 
+#[test]
+fn dep_matches() {
+    #[realia::dep_matches("realia", "syn", "^1.0.30, <2")]
+    fn positive() -> bool {
+        true
+    }
+    assert!(positive());
+
+    #[realia::not(dep_matches("realia", "syn", "~1.1"))]
+    fn negative() -> bool {
+        true
+    }
+    assert!(negative());
+}
+
+#[test]
+fn dep_version_of_linked_copy() {
+    // Serde's derive macro pulls in a newer major version of Syn,
+    // but Realia itself links Syn 1.x.
+    #[realia::all(dep_since("realia", "syn", "1"), dep_before("realia", "syn", "2"))]
+    fn positive() -> bool {
+        true
+    }
+    assert!(positive());
+}
+
 #[test]
 fn dep_from_registry() {
     #[realia::dep_from_registry("realia", "syn")]
@@ -124,6 +221,291 @@ fn dep_from_registry() {
     assert!(negative());
 }
 
+#[test]
+fn dep_source() {
+    #[realia::all(
+        dep_source("realia", "syn", "registry"),
+        dep_source("realia", "syn", "registry:crates-io"),
+        dep_source("realia", "unicode-ident", "registry", transitive = true),
+        dep_source("realia", "realia", "path")
+    )]
+    fn positive() -> bool {
+        true
+    }
+    assert!(positive());
+
+    #[realia::not(any(dep_source("realia", "syn", "git"), dep_source("realia", "syn", "path"),))]
+    fn negative() -> bool {
+        true
+    }
+    assert!(negative());
+}
+
+#[test]
+fn dep_git() {
+    #[realia::not(any(
+        dep_git("realia", "syn"),
+        dep_git("realia", "syn", rev = "4a5b3c2"),
+        dep_git("realia", "syn", branch = "master", tag = "1.0.0"),
+    ))]
+    fn negative() -> bool {
+        true
+    }
+    assert!(negative());
+}
+
+#[test]
+fn dep_patched() {
+    #[realia::not(any(
+        dep_patched("realia", "syn"),
+        dep_patched("realia", "realia"),
+        dep_patched("realia", "unicode-ident", transitive = true),
+    ))]
+    fn negative() -> bool {
+        true
+    }
+    assert!(negative());
+}
+
+#[test]
+fn dep_links() {
+    // The fixture is a dev-dependency, so it's only linked into tests.
+    #[realia::dep_links("realia", "realia_fixture")]
+    fn positive() -> bool {
+        true
+    }
+    assert!(positive());
+
+    #[realia::not(any(dep_links("realia", "openssl"), dep_links("realia", "z")))]
+    fn negative() -> bool {
+        true
+    }
+    assert!(negative());
+}
+
+#[test]
+fn dep_metadata() {
+    #[realia::all(
+        dep_metadata("realia", "realia-links-fixture", "realia-test"),
+        dep_metadata("realia", "realia-links-fixture", "realia-test.api_level"),
+        dep_metadata("realia", "realia-links-fixture", "realia-test.api_level", 3),
+        dep_metadata("realia", "realia-links-fixture", "realia-test.api_level", "3"),
+        dep_metadata("realia", "realia-links-fixture", "realia-test.stable", true),
+        dep_metadata("realia", "realia-links-fixture", "realia-test.tier", "gold"),
+        dep_metadata("realia", "realia-links-fixture", "realia-test.ratio", 0.5),
+        dep_metadata("realia-links-fixture", "realia-test.api_level", 3)
+    )]
+    fn positive() -> bool {
+        true
+    }
+    assert!(positive());
+
+    #[realia::not(any(
+        dep_metadata("realia", "realia-links-fixture", "realia-test.missing"),
+        dep_metadata("realia", "realia-links-fixture", "realia-test.api_level", 4),
+        dep_metadata("realia", "realia-links-fixture", "realia-test.stable", false),
+        dep_metadata("realia", "realia-links-fixture", "realia-test.tier", "silver"),
+        dep_metadata("realia", "syn", "realia-test"),
+        dep_metadata("realia", "realia", "realia-test"),
+    ))]
+    fn negative() -> bool {
+        true
+    }
+    assert!(negative());
+}
+
+#[test]
+fn dep_msrv() {
+    #[realia::all(
+        dep_msrv_since("realia", "syn", "1.31"),
+        dep_msrv_since("realia", "syn", "1.30.1"),
+        dep_msrv_before("realia", "syn", "1.32"),
+        dep_msrv_supported("realia", "syn"),
+        dep_msrv_supported("realia", "realia"),
+        dep_msrv_supported("realia", "version-compare"),
+        dep_msrv_since("realia", "realia", "1.63"),
+        dep_msrv_before("realia", "realia", "1.64"),
+        dep_msrv_since("realia", "realia-msrv-fixture", "1.56", transitive = true),
+        dep_msrv_before("realia", "realia-msrv-fixture", "1.57", transitive = true),
+        dep_msrv_since("syn", "1.31")
+    )]
+    fn positive() -> bool {
+        true
+    }
+    assert!(positive());
+
+    #[realia::not(any(
+        dep_msrv_since("realia", "syn", "1.32"),
+        dep_msrv_before("realia", "syn", "1.31"),
+        dep_msrv_since("realia", "realia", "1.64"),
+        dep_msrv_before("realia", "realia", "1.63"),
+        dep_msrv_since("realia", "version-compare", "1.0"),
+        // Only an indirect dependency.
+        dep_msrv_since("realia", "realia-msrv-fixture", "1.56"),
+        dep_msrv_since("realia", "realia-msrv-fixture", "1.57", transitive = true),
+        // Doesn't declare a `rust-version`.
+        dep_msrv_since("realia", "realia-links-fixture", "1.0"),
+    ))]
+    fn negative() -> bool {
+        true
+    }
+    assert!(negative());
+}
+
+#[test]
+fn dep_renamed() {
+    #[realia::dep_renamed("realia", "renamed-itoa")]
+    fn positive() -> bool {
+        true
+    }
+    assert!(positive());
+
+    #[realia::not(dep_renamed("realia", "syn"))]
+    fn negative() -> bool {
+        true
+    }
+    assert!(negative());
+}
+
+#[test]
+fn dep_exists_renamed() {
+    #[realia::dep_since("realia", "renamed-itoa", "1.0.0")]
+    fn positive() -> bool {
+        true
+    }
+    assert!(positive());
+
+    // The anchor's own names still apply when searching transitively.
+    #[realia::all(
+        dep("realia", "renamed-itoa", transitive = true),
+        dep_since("realia", "renamed-itoa", "1.0.0", transitive = true),
+        dep_transitive("realia", "renamed-itoa")
+    )]
+    fn transitive() -> bool {
+        true
+    }
+    assert!(transitive());
+}
+
+#[test]
+fn dep_transitive() {
+    #[realia::all(
+        dep_transitive("realia", "unicode-ident"),
+        dep_transitive("realia", "syn"),
+        dep("realia", "unicode-ident", transitive = true),
+        dep_since("realia", "unicode-ident", "1", transitive = true),
+        dep_before("realia", "unicode-ident", "2", transitive = true),
+        dep_matches("realia", "unicode-ident", "1", transitive = true)
+    )]
+    fn positive() -> bool {
+        true
+    }
+    assert!(positive());
+
+    #[realia::not(any(
+        dep("realia", "unicode-ident"),
+        dep_transitive("realia", "openssl-sys"),
+        dep_transitive("realia", "unicode-ident", "0.0.1"),
+        dep_since("realia", "unicode-ident", "2", transitive = true),
+    ))]
+    fn negative() -> bool {
+        true
+    }
+    assert!(negative());
+}
+
+#[test]
+fn dep_copies() {
+    #[realia::all(
+        dep_since("realia", "semver", "0.9", transitive = true, copies = "all"),
+        dep_since("realia", "semver", "1", transitive = true, copies = "one"),
+        dep_before("realia", "semver", "1", transitive = true, copies = "one"),
+        dep_matches("realia", "syn", "1", copies = "all")
+    )]
+    fn positive() -> bool {
+        true
+    }
+    assert!(positive());
+
+    #[realia::not(any(
+        dep_since("realia", "semver", "1", transitive = true, copies = "all"),
+        dep_since("realia", "semver", "0.9", transitive = true, copies = "one"),
+        dep_since("realia", "not-a-dependency", "0.1", copies = "all"),
+    ))]
+    fn negative() -> bool {
+        true
+    }
+    assert!(negative());
+}
+
+#[test]
+fn dep_duplicated() {
+    #[realia::dep_duplicated("realia", "semver")]
+    fn positive() -> bool {
+        true
+    }
+    assert!(positive());
+
+    #[realia::not(dep_duplicated("realia", "quote"))]
+    fn negative() -> bool {
+        true
+    }
+    assert!(negative());
+}
+
+#[test]
+fn dep_kind() {
+    #[realia::all(
+        dep_kind("realia", "syn", "normal"),
+        dep_kind("realia", "version-compare", "dev"),
+        dep("realia", "version-compare")
+    )]
+    fn positive() -> bool {
+        true
+    }
+    assert!(positive());
+
+    // Declared, even though the optional dependency isn't enabled.
+    #[allow(deprecated)] // The anchor is the fixture rather than the crate being compiled.
+    #[realia::all(
+        dep_kind("realia-optional-fixture", "ryu", "normal"),
+        not(dep("realia-optional-fixture", "ryu"))
+    )]
+    fn declared() -> bool {
+        true
+    }
+    assert!(declared());
+
+    #[realia::not(any(dep_kind("realia", "syn", "dev"), dep_kind("realia", "version-compare", "build")))]
+    fn negative() -> bool {
+        true
+    }
+    assert!(negative());
+}
+
+#[test]
+fn dep_feature() {
+    #[realia::all(
+        dep_feature("realia", "syn", "full"),
+        dep_feature("realia", "syn", "default"),
+        dep_feature("realia", "serde", "std", transitive = true)
+    )]
+    fn positive() -> bool {
+        true
+    }
+    assert!(positive());
+
+    #[realia::not(any(
+        dep_feature("realia", "syn", "fold"),
+        dep_feature("realia", "serde", "std"),
+        dep_feature("realia", "not-a-dependency", "full"),
+    ))]
+    fn negative() -> bool {
+        true
+    }
+    assert!(negative());
+}
+
 #[test]
 fn any() {
     #[realia::any(env("REALIA_EMPTY"), env("REALIA_UNDEFINED"))]
@@ -158,7 +540,7 @@ fn all() {
 fn attr() {
     #[realia::attr(env("REALIA_EMPTY"), derive(Debug))]
     struct Empty;
-    format!("{:?}", Empty {});
+    let _ = format!("{:?}", Empty {});
 }
 
 #[test]
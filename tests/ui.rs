@@ -0,0 +1,61 @@
+use std::env;
+use trybuild::TestCases;
+
+#[test]
+fn ui() {
+    env::remove_var("REALIA_STRICT");
+    TestCases::new().compile_fail("tests/ui/*.rs");
+
+    // Strict mode is read while the macros run, so each setting gets its own batch.
+    for (mode, dir, fails) in &[
+        ("1", "error", true),
+        ("warn", "warn", true),
+        ("yes", "invalid", true),
+        ("0", "off", false),
+    ] {
+        env::set_var("REALIA_STRICT", mode);
+        let t = TestCases::new();
+        let files = format!("tests/ui/strict/{}/*.rs", dir);
+        if *fails {
+            t.compile_fail(files);
+        } else {
+            t.pass(files);
+        }
+    }
+    env::remove_var("REALIA_STRICT");
+
+    sandboxed();
+}
+
+/// Runs the checks where neither Cargo nor rustc can be spawned by the macros.
+#[cfg(unix)]
+fn sandboxed() {
+    use std::{fs, os::unix::fs::PermissionsExt, path::Path};
+
+    // Trybuild always passes its own host as `--target`, so that's dropped too,
+    // making the macros fall back to the host that Realia was built for.
+    let script = r#"#!/bin/sh
+export CARGO=/bin/false RUSTC=/bin/false REALIA_NO_CACHE=1
+skip=
+for arg; do
+    shift
+    if [ -n "$skip" ]; then skip=; continue; fi
+    case "$arg" in
+        --target) skip=1; continue ;;
+        --target=*) continue ;;
+    esac
+    set -- "$@" "$arg"
+done
+exec "$@"
+"#;
+    let wrapper = Path::new(env!("CARGO_TARGET_TMPDIR")).join("sandboxed-rustc.sh");
+    fs::write(&wrapper, script).unwrap();
+    fs::set_permissions(&wrapper, fs::Permissions::from_mode(0o755)).unwrap();
+
+    env::set_var("RUSTC_WRAPPER", &wrapper);
+    TestCases::new().compile_fail("tests/ui/sandboxed/*.rs");
+    env::remove_var("RUSTC_WRAPPER");
+}
+
+#[cfg(not(unix))]
+fn sandboxed() {}
@@ -0,0 +1,8 @@
+#![deny(deprecated)]
+
+#[realia::dep("realia", "syn")]
+fn example() {}
+
+fn main() {
+    example();
+}
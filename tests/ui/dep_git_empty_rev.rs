@@ -0,0 +1,4 @@
+#[realia::dep_git("realia", "syn", rev = "")]
+fn example() {}
+
+fn main() {}
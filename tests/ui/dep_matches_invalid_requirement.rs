@@ -0,0 +1,4 @@
+#[realia::dep_matches("realia", "syn", ">= 1, <")]
+fn example() {}
+
+fn main() {}
@@ -0,0 +1,4 @@
+#[realia::dep_metadata("realia", "ourcorp.api_level", "3")]
+fn example() {}
+
+fn main() {}
@@ -0,0 +1,4 @@
+#[realia::dep_since("realia", "syn", "1.x")]
+fn example() {}
+
+fn main() {}
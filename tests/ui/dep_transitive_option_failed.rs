@@ -0,0 +1,4 @@
+#[realia::dep("reaila", "syn", transitive = true)]
+fn example() {}
+
+fn main() {}
@@ -0,0 +1,4 @@
+#[realia::dep("reaila", "syn")]
+fn example() {}
+
+fn main() {}
@@ -0,0 +1,4 @@
+#[realia::dep("realia-test", "syn")]
+fn example() {}
+
+fn main() {}
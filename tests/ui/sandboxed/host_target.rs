@@ -0,0 +1,10 @@
+// Neither Cargo nor rustc can be run here, so the target comes from the build
+// and the dependencies come from Cargo.lock and Cargo.toml, with a warning.
+#![deny(deprecated)]
+
+#[realia::dep("syn")]
+fn example() {}
+
+fn main() {
+    example();
+}
@@ -0,0 +1,4 @@
+#[realia::dep("realia", "synn")]
+fn example() {}
+
+fn main() {}
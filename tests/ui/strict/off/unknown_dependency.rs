@@ -0,0 +1,6 @@
+#[realia::not(dep("realia", "synn"))]
+fn example() {}
+
+fn main() {
+    example();
+}
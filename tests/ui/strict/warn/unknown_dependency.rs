@@ -0,0 +1,8 @@
+#![deny(deprecated)]
+
+#[realia::not(dep("realia", "synn"))]
+fn example() {}
+
+fn main() {
+    example();
+}